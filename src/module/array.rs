@@ -0,0 +1,495 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::number;
+use super::util::{array, cell, get_callback, get_integer, position, receiver, type_error};
+use super::var::{ArrayRef, FuncValue, ValueType, Var};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+fn this_array(this: Option<Rc<RefCell<Var>>>) -> Result<ArrayRef, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Array(val) => Ok(val.clone()),
+    _ => Err(type_error()),
+  }
+}
+/// 复制当前的元素。回调可能修改数组，因此调用回调期间不能持有数组的借用。
+fn items(arr: &ArrayRef) -> Vec<Var> {
+  arr
+    .borrow()
+    .iter()
+    .map(|item| item.borrow().clone())
+    .collect()
+}
+fn equals(left: &Var, right: &Var) -> bool {
+  matches!(left.clone().opcall("===", right), Ok(Var::Boolean(true)))
+}
+fn truthy(val: Var) -> Result<bool, LppError> {
+  val.try_into().map_err(LppError::Error)
+}
+/// 以 `(元素, 下标, 数组)` 为参数调用回调。
+fn call<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  func: &FuncValue,
+  arr: &ArrayRef,
+  index: usize,
+  item: Var,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.runfunc(
+    func,
+    vec![item, Var::Integer(index as i64), Var::Array(arr.clone())],
+  )
+}
+fn length<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(this_array(this)?.borrow().len() as i64))
+}
+fn push<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let arr = this_array(this)?;
+  let mut arr = arr.borrow_mut();
+  arr.extend(args.into_iter().map(cell));
+  Ok(Var::Integer(arr.len() as i64))
+}
+fn pop<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  let item = this_array(this)?.borrow_mut().pop();
+  Ok(match item {
+    Some(item) => item.borrow().clone(),
+    None => Var::Null(()),
+  })
+}
+fn shift<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  let arr = this_array(this)?;
+  let item = if arr.borrow().is_empty() {
+    None
+  } else {
+    Some(arr.borrow_mut().remove(0))
+  };
+  Ok(match item {
+    Some(item) => item.borrow().clone(),
+    None => Var::Null(()),
+  })
+}
+fn unshift<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let arr = this_array(this)?;
+  let mut arr = arr.borrow_mut();
+  arr.splice(0..0, args.into_iter().map(cell));
+  Ok(Var::Integer(arr.len() as i64))
+}
+fn splice<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let arr = this_array(this)?;
+  let len = arr.borrow().len();
+  let start = position(get_integer(&args, 0)?.unwrap_or(0), len);
+  let count = match get_integer(&args, 1)? {
+    Some(count) if count <= 0 => 0,
+    Some(count) => (count as usize).min(len - start),
+    None => len - start,
+  };
+  let removed: Vec<Rc<RefCell<Var>>> = arr
+    .borrow_mut()
+    .splice(start..start + count, args.into_iter().skip(2).map(cell))
+    .collect();
+  Ok(Var::from(removed))
+}
+fn concat<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let mut ret = items(&this_array(this)?);
+  for item in args {
+    match item {
+      Var::Array(val) => ret.extend(items(&val)),
+      item => ret.push(item),
+    }
+  }
+  Ok(array(ret))
+}
+fn index_of<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let items = items(&this_array(this)?);
+  let val = args.first().cloned().unwrap_or(Var::Null(()));
+  let from = position(get_integer(&args, 1)?.unwrap_or(0), items.len());
+  Ok(Var::Integer(
+    match items.iter().skip(from).position(|item| equals(item, &val)) {
+      Some(index) => (from + index) as i64,
+      None => -1,
+    },
+  ))
+}
+fn includes<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = args.first().cloned().unwrap_or(Var::Null(()));
+  Ok(Var::Boolean(
+    items(&this_array(this)?)
+      .iter()
+      .any(|item| equals(item, &val)),
+  ))
+}
+fn join<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let sep = match args.first() {
+    None | Some(Var::Null(_)) => String::from(","),
+    Some(Var::String(val)) => val.clone(),
+    Some(_) => return Err(LppError::Error(Error::from("String expected"))),
+  };
+  Ok(Var::String(
+    items(&this_array(this)?)
+      .into_iter()
+      .map(|item| match item {
+        Var::Null(_) => String::new(),
+        item => number::plain(&item),
+      })
+      .collect::<Vec<String>>()
+      .join(sep.as_str()),
+  ))
+}
+fn reverse<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  let arr = this_array(this)?;
+  arr.borrow_mut().reverse();
+  Ok(Var::Array(arr))
+}
+fn map<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  let mut ret = vec![];
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    ret.push(call(handler, &func, &arr, index, item)?);
+  }
+  Ok(array(ret))
+}
+fn filter<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  let mut ret = vec![];
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    if truthy(call(handler, &func, &arr, index, item.clone())?)? {
+      ret.push(item);
+    }
+  }
+  Ok(array(ret))
+}
+fn reduce<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  let mut items = items(&arr).into_iter().enumerate();
+  let mut acc = match args.get(1) {
+    Some(val) => val.clone(),
+    None => match items.next() {
+      Some((_, item)) => item,
+      None => {
+        return Err(LppError::Error(Error::from(
+          "Reduce of empty array with no initial value",
+        )))
+      }
+    },
+  };
+  for (index, item) in items {
+    acc = handler.runfunc(
+      &func,
+      vec![
+        acc,
+        item,
+        Var::Integer(index as i64),
+        Var::Array(arr.clone()),
+      ],
+    )?;
+  }
+  Ok(acc)
+}
+fn find<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    if truthy(call(handler, &func, &arr, index, item.clone())?)? {
+      return Ok(item);
+    }
+  }
+  Ok(Var::Null(()))
+}
+fn some<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    if truthy(call(handler, &func, &arr, index, item)?)? {
+      return Ok(Var::Boolean(true));
+    }
+  }
+  Ok(Var::Boolean(false))
+}
+fn every<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    if !truthy(call(handler, &func, &arr, index, item)?)? {
+      return Ok(Var::Boolean(false));
+    }
+  }
+  Ok(Var::Boolean(true))
+}
+fn for_each<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = get_callback(&args, 0)?;
+  for (index, item) in items(&arr).into_iter().enumerate() {
+    call(handler, &func, &arr, index, item)?;
+  }
+  Ok(Var::Null(()))
+}
+/// 稳定的归并排序。比较函数来自脚本，可能出错或不满足全序，
+/// 因此不使用标准库的排序（比较不一致时可能 panic）。
+fn merge_sort<T: Clone>(
+  items: Vec<T>,
+  cmp: &mut impl FnMut(&T, &T) -> Result<Ordering, LppError>,
+) -> Result<Vec<T>, LppError> {
+  if items.len() <= 1 {
+    return Ok(items);
+  }
+  let mut left = items;
+  let right = left.split_off(left.len() / 2);
+  let left = merge_sort(left, cmp)?;
+  let right = merge_sort(right, cmp)?;
+  let mut ret = Vec::with_capacity(left.len() + right.len());
+  let (mut i, mut j) = (0, 0);
+  while i < left.len() && j < right.len() {
+    if cmp(&right[j], &left[i])? == Ordering::Less {
+      ret.push(right[j].clone());
+      j += 1;
+    } else {
+      ret.push(left[i].clone());
+      i += 1;
+    }
+  }
+  ret.extend_from_slice(&left[i..]);
+  ret.extend_from_slice(&right[j..]);
+  Ok(ret)
+}
+fn sort<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr = this_array(this)?;
+  let func = match args.first() {
+    None | Some(Var::Null(_)) => None,
+    Some(_) => Some(get_callback(&args, 0)?),
+  };
+  let cells: Vec<Rc<RefCell<Var>>> = arr.borrow().clone();
+  let sorted = merge_sort(cells, &mut |left, right| {
+    let (left, right) = (left.borrow().clone(), right.borrow().clone());
+    match &func {
+      Some(func) => {
+        let ret: f64 = handler
+          .runfunc(func, vec![left, right])?
+          .try_into()
+          .map_err(LppError::Error)?;
+        Ok(ret.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+      }
+      None => {
+        if truthy(left.clone().opcall("<", &right).map_err(LppError::Error)?)? {
+          Ok(Ordering::Less)
+        } else if truthy(left.opcall(">", &right).map_err(LppError::Error)?)? {
+          Ok(Ordering::Greater)
+        } else {
+          Ok(Ordering::Equal)
+        }
+      }
+    }
+  })?;
+  *arr.borrow_mut() = sorted;
+  Ok(Var::Array(arr))
+}
+/// 在 `Handler` 中注册数组的方法。
+/// 修改数组的方法直接作用于数组的存储，对所有引用可见；
+/// 回调以 `(元素, 下标, 数组)` 为参数，经 `runfunc` 调用。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let arr: &[ValueType] = &[ValueType::Array];
+  handler.add_native(
+    "length",
+    NativeFunc::from((arr, length::<Parser> as _, true)),
+  );
+  let methods: [(&str, BuiltinFunc<Parser>); 18] = [
+    ("push", push::<Parser>),
+    ("pop", pop::<Parser>),
+    ("shift", shift::<Parser>),
+    ("unshift", unshift::<Parser>),
+    ("splice", splice::<Parser>),
+    ("concat", concat::<Parser>),
+    ("indexOf", index_of::<Parser>),
+    ("includes", includes::<Parser>),
+    ("join", join::<Parser>),
+    ("reverse", reverse::<Parser>),
+    ("map", map::<Parser>),
+    ("filter", filter::<Parser>),
+    ("reduce", reduce::<Parser>),
+    ("find", find::<Parser>),
+    ("some", some::<Parser>),
+    ("every", every::<Parser>),
+    ("sort", sort::<Parser>),
+    ("forEach", for_each::<Parser>),
+  ];
+  for (name, func) in methods {
+    handler.add_native(name, NativeFunc::from((arr, func, false)));
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{handler, method, show, var};
+  use super::*;
+  #[test]
+  fn mutators_work_in_place() {
+    let handler = handler();
+    let arr = var("[1,2,3]");
+    let run = |name: &str, args: Vec<Var>| method(&handler, arr.clone(), name, args).to_string();
+    assert_eq!(run("push", vec![var("4"), var("5")]), "5");
+    assert_eq!(run("pop", vec![]), "5");
+    assert_eq!(run("shift", vec![]), "1");
+    assert_eq!(run("unshift", vec![var("0")]), "4");
+    assert_eq!(arr.to_string(), "[0,2,3,4]");
+    assert_eq!(
+      run("splice", vec![var("1"), var("2"), var("\"x\"")]),
+      "[2,3]"
+    );
+    assert_eq!(arr.to_string(), "[0,\"x\",4]");
+    assert_eq!(run("reverse", vec![]), "[4,\"x\",0]");
+    assert_eq!(arr.to_string(), "[4,\"x\",0]");
+    let nums = var("[10,9,1,2]");
+    method(&handler, nums.clone(), "sort", vec![]);
+    assert_eq!(nums.to_string(), "[1,2,9,10]");
+  }
+  #[test]
+  fn queries_return_new_values() {
+    let handler = handler();
+    let arr = var("[1,2,[3]]");
+    let run = |name: &str, args: Vec<Var>| method(&handler, arr.clone(), name, args).to_string();
+    assert_eq!(run("indexOf", vec![var("2")]), "1");
+    assert_eq!(run("indexOf", vec![var("[3]")]), "2");
+    assert_eq!(run("includes", vec![var("1")]), "true");
+    assert_eq!(run("join", vec![var("\"-\"")]), "\"1-2-[3]\"");
+    assert_eq!(run("concat", vec![var("[4]"), var("5")]), "[1,2,[3],4,5]");
+    assert_eq!(arr.to_string(), "[1,2,[3]]");
+    assert_eq!(run("length", vec![]), "3");
+  }
+  #[test]
+  fn callbacks_receive_each_element() {
+    let handler = handler();
+    let arr = var("[1,2]");
+    let noop = var("func(item,index,array){}");
+    assert_eq!(
+      method(&handler, arr.clone(), "map", vec![noop.clone()]).to_string(),
+      "[null,null]"
+    );
+    let ret = handler.call_native(
+      "filter",
+      Rc::new(RefCell::new(arr.clone())),
+      vec![noop.clone()],
+    );
+    assert_eq!(show(ret), "error: Conversion failed");
+    assert_eq!(
+      method(
+        &handler,
+        arr.clone(),
+        "reduce",
+        vec![noop.clone(), var("0")]
+      )
+      .to_string(),
+      "null"
+    );
+    let strict = var("func(a,b,c,d){}");
+    let ret = handler.call_native("map", Rc::new(RefCell::new(arr.clone())), vec![strict]);
+    assert_eq!(show(ret), "error: Too few arguments given");
+    let ret = handler.call_native("map", Rc::new(RefCell::new(arr)), vec![var("1")]);
+    assert_eq!(show(ret), "error: Function expected");
+  }
+}
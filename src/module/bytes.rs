@@ -0,0 +1,265 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::util::{get_integer, position, receiver, type_error};
+use super::var::{ValueType, Var};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::cell::RefCell;
+use std::rc::Rc;
+/// 字节串与字符串之间的编码方式。
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+  Utf8,
+  Hex,
+  Base64,
+}
+impl Encoding {
+  /// 由编码名解析编码方式。
+  /// ```
+  /// assert!(Encoding::parse("base64").unwrap() == Encoding::Base64);
+  /// ```
+  pub fn parse(str: &str) -> Result<Self, Error> {
+    match str.to_ascii_lowercase().as_str() {
+      "utf8" | "utf-8" => Ok(Encoding::Utf8),
+      "hex" => Ok(Encoding::Hex),
+      "base64" => Ok(Encoding::Base64),
+      _ => Err(Error::new(format!("Unknown encoding: {}", str))),
+    }
+  }
+}
+/// 将字节串编码为字符串。
+/// ```
+/// assert_eq!(bytes::encode(b"awa", Encoding::Hex).unwrap(), "617761");
+/// assert_eq!(bytes::encode(b"awa", Encoding::Base64).unwrap(), "YXdh");
+/// ```
+pub fn encode(val: &[u8], encoding: Encoding) -> Result<String, Error> {
+  match encoding {
+    Encoding::Utf8 => match String::from_utf8(val.to_vec()) {
+      Ok(val) => Ok(val),
+      Err(_) => Err(Error::from("Invalid UTF-8 sequence")),
+    },
+    Encoding::Hex => Ok(val.iter().map(|item| format!("{:02x}", item)).collect()),
+    Encoding::Base64 => Ok(STANDARD.encode(val)),
+  }
+}
+/// 将字符串解码为字节串。
+/// ```
+/// assert_eq!(bytes::decode("617761", Encoding::Hex).unwrap(), b"awa");
+/// ```
+pub fn decode(str: &str, encoding: Encoding) -> Result<Vec<u8>, Error> {
+  match encoding {
+    Encoding::Utf8 => Ok(str.as_bytes().to_vec()),
+    Encoding::Hex => {
+      if !str.len().is_multiple_of(2) || !str.chars().all(|item| item.is_ascii_hexdigit()) {
+        return Err(Error::from("Invalid hex string"));
+      }
+      let mut ret: Vec<u8> = vec![];
+      for index in (0..str.len()).step_by(2) {
+        match u8::from_str_radix(&str[index..index + 2], 16) {
+          Ok(val) => ret.push(val),
+          Err(_) => return Err(Error::from("Invalid hex string")),
+        }
+      }
+      Ok(ret)
+    }
+    Encoding::Base64 => match STANDARD.decode(str) {
+      Ok(val) => Ok(val),
+      Err(_) => Err(Error::from("Invalid base64 string")),
+    },
+  }
+}
+/// 获取指定下标处的字节。越界时返回 `None`。
+pub fn at(val: &[u8], index: i64) -> Option<u8> {
+  if index < 0 && index.unsigned_abs() as usize > val.len() {
+    None
+  } else {
+    val.get(position(index, val.len())).copied()
+  }
+}
+/// 截取 `[start, end)` 范围内的字节。
+/// ```
+/// assert_eq!(bytes::slice(b"awawa", 1, Some(-1)), b"waw");
+/// ```
+pub fn slice(val: &[u8], start: i64, end: Option<i64>) -> Vec<u8> {
+  let start = position(start, val.len());
+  let end = match end {
+    Some(end) => position(end, val.len()),
+    None => val.len(),
+  };
+  if start < end {
+    val[start..end].to_vec()
+  } else {
+    vec![]
+  }
+}
+/// 下标访问 `b[i]`：返回下标处的字节，越界时返回 `null`。`val` 不是字节串时返回 `None`。
+pub fn index(val: &Var, index: i64) -> Option<Var> {
+  match val {
+    Var::Bytes(val) => Some(match at(val, index) {
+      Some(item) => Var::Integer(item as i64),
+      None => Var::Null(()),
+    }),
+    _ => None,
+  }
+}
+fn this_bytes(this: Option<Rc<RefCell<Var>>>) -> Result<Vec<u8>, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Bytes(val) => Ok(val.clone()),
+    _ => Err(type_error()),
+  }
+}
+fn get_encoding(args: &[Var], index: usize) -> Result<Encoding, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(Encoding::Utf8),
+    Some(Var::String(val)) => Encoding::parse(val.as_str()).map_err(LppError::Error),
+    _ => Err(LppError::Error(Error::from("Encoding name expected"))),
+  }
+}
+fn bytes_from<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(Var::String(val)) => Ok(Var::Bytes(
+      decode(val.as_str(), get_encoding(&args, 1)?).map_err(LppError::Error)?,
+    )),
+    Some(val) => Ok(
+      val
+        .clone()
+        .convert(ValueType::Bytes)
+        .map_err(LppError::Error)?,
+    ),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// `b.toString(encoding)`，默认按 UTF-8 解码。
+fn to_string<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(
+    encode(&this_bytes(this)?, get_encoding(&args, 0)?).map_err(LppError::Error)?,
+  ))
+}
+fn bytes_at<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match at(&this_bytes(this)?, get_integer(&args, 0)?.unwrap_or(0)) {
+    Some(item) => Ok(Var::Integer(item as i64)),
+    None => Ok(Var::Null(())),
+  }
+}
+fn bytes_slice<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Bytes(slice(
+    &this_bytes(this)?,
+    get_integer(&args, 0)?.unwrap_or(0),
+    get_integer(&args, 1)?,
+  )))
+}
+fn length<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(this_bytes(this)?.len() as i64))
+}
+/// 在 `Handler` 中注册构造函数 `Bytes.from` 与字节串的方法
+/// `toString`、`at`、`slice` 与 `length`。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let bytes: &[ValueType] = &[ValueType::Bytes];
+  handler.add_builtin("Bytes.from", bytes_from::<Parser>);
+  handler.add_native(
+    "length",
+    NativeFunc::from((bytes, length::<Parser> as _, true)),
+  );
+  handler.add_native(
+    "toString",
+    NativeFunc::from((bytes, to_string::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "at",
+    NativeFunc::from((bytes, bytes_at::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "slice",
+    NativeFunc::from((bytes, bytes_slice::<Parser> as _, false)),
+  );
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, method, show, var};
+  use super::*;
+  #[test]
+  fn literals_and_encodings_round_trip() {
+    let handler = handler();
+    let val = call(&handler, "Bytes.from", vec![var("\"awa\"")]);
+    assert_eq!(val.to_string(), "x\"617761\"");
+    assert!(matches!(var("x\"617761\""), Var::Bytes(item) if item == b"awa"));
+    let val = call(
+      &handler,
+      "Bytes.from",
+      vec![var("\"YXdh\""), var("\"base64\"")],
+    );
+    assert_eq!(
+      method(&handler, val.clone(), "toString", vec![var("\"hex\"")]).to_string(),
+      "\"617761\""
+    );
+    assert_eq!(
+      method(&handler, val, "toString", vec![]).to_string(),
+      "\"awa\""
+    );
+    assert_eq!(
+      show(handler.call_builtin("Bytes.from", None, vec![var("\"6\""), var("\"hex\"")])),
+      show(Err(LppError::Error(
+        decode("6", Encoding::Hex).err().unwrap()
+      )))
+    );
+    assert!(handler
+      .call_builtin("Bytes.from", None, vec![var("\"a\""), var("\"utf16\"")])
+      .is_err());
+    assert!(handler
+      .call_native(
+        "toString",
+        Rc::new(RefCell::new(Var::Bytes(vec![0xff]))),
+        vec![]
+      )
+      .is_err());
+  }
+  #[test]
+  fn indexing_and_slicing() {
+    let handler = handler();
+    let val = var("x\"617761\"");
+    assert_eq!(
+      method(&handler, val.clone(), "length", vec![]).to_string(),
+      "3"
+    );
+    assert_eq!(
+      method(&handler, val.clone(), "at", vec![Var::Integer(-1)]).to_string(),
+      "97"
+    );
+    assert_eq!(
+      method(&handler, val.clone(), "at", vec![var("5")]).to_string(),
+      "null"
+    );
+    assert_eq!(
+      method(&handler, val.clone(), "slice", vec![var("1")]).to_string(),
+      "x\"7761\""
+    );
+    assert_eq!(
+      index(&val, 1).map(|item| item.to_string()),
+      Some(String::from("119"))
+    );
+    assert!(index(&var("[1]"), 0).is_none());
+  }
+}
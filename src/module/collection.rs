@@ -0,0 +1,261 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::util::{array, receiver, type_error};
+use super::var::{ValueType, Var, VarKey};
+use indexmap::{IndexMap, IndexSet};
+use std::cell::RefCell;
+use std::rc::Rc;
+fn key_arg(args: &[Var], index: usize) -> Result<VarKey, LppError> {
+  match args.get(index) {
+    Some(val) => VarKey::try_from(val).map_err(LppError::Error),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+fn map_new<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.into_iter().next() {
+    None | Some(Var::Null(_)) => Ok(Var::from(IndexMap::<VarKey, Rc<RefCell<Var>>>::new())),
+    Some(val) => val.convert(ValueType::Map).map_err(LppError::Error),
+  }
+}
+fn set_new<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.into_iter().next() {
+    None | Some(Var::Null(_)) => Ok(Var::Set(IndexSet::new())),
+    Some(val) => val.convert(ValueType::Set).map_err(LppError::Error),
+  }
+}
+fn size<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Map(val) => Ok(Var::Integer(val.borrow().len() as i64)),
+    Var::Set(val) => Ok(Var::Integer(val.len() as i64)),
+    _ => Err(type_error()),
+  }
+}
+fn has<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let key = key_arg(&args, 0)?;
+  match &*receiver(this)?.borrow() {
+    Var::Map(val) => Ok(Var::Boolean(val.borrow().contains_key(&key))),
+    Var::Set(val) => Ok(Var::Boolean(val.contains(&key))),
+    _ => Err(type_error()),
+  }
+}
+fn get<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let key = key_arg(&args, 0)?;
+  if let Var::Map(val) = &*receiver(this)?.borrow() {
+    match val.borrow().get(&key) {
+      Some(item) => Ok(item.borrow().clone()),
+      None => Ok(Var::Null(())),
+    }
+  } else {
+    Err(type_error())
+  }
+}
+fn set<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let key = key_arg(&args, 0)?;
+  let value = args.get(1).cloned().unwrap_or(Var::Null(()));
+  if let Var::Map(val) = &*receiver(this)?.borrow() {
+    let mut val = val.borrow_mut();
+    match val.get(&key) {
+      Some(item) => *item.borrow_mut() = value,
+      None => {
+        val.insert(key, Rc::new(RefCell::new(value)));
+      }
+    }
+    Ok(Var::Null(()))
+  } else {
+    Err(type_error())
+  }
+}
+fn add<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let key = key_arg(&args, 0)?;
+  if let Var::Set(val) = &mut *receiver(this)?.borrow_mut() {
+    val.insert(key);
+    Ok(Var::Null(()))
+  } else {
+    Err(type_error())
+  }
+}
+fn delete<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let key = key_arg(&args, 0)?;
+  match &mut *receiver(this)?.borrow_mut() {
+    Var::Map(val) => Ok(Var::Boolean(val.borrow_mut().shift_remove(&key).is_some())),
+    Var::Set(val) => Ok(Var::Boolean(val.shift_remove(&key))),
+    _ => Err(type_error()),
+  }
+}
+fn clear<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &mut *receiver(this)?.borrow_mut() {
+    Var::Map(val) => val.borrow_mut().clear(),
+    Var::Set(val) => val.clear(),
+    _ => return Err(type_error()),
+  }
+  Ok(Var::Null(()))
+}
+fn keys<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Map(val) => Ok(array(val.borrow().keys().map(Var::from).collect())),
+    Var::Set(val) => Ok(array(val.iter().map(Var::from).collect())),
+    _ => Err(type_error()),
+  }
+}
+fn values<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Map(val) => Ok(array(
+      val
+        .borrow()
+        .values()
+        .map(|item| item.borrow().clone())
+        .collect(),
+    )),
+    Var::Set(val) => Ok(array(val.iter().map(Var::from).collect())),
+    _ => Err(type_error()),
+  }
+}
+fn entries<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Map(val) => Ok(array(
+      val
+        .borrow()
+        .iter()
+        .map(|(key, value)| array(vec![Var::from(key), value.borrow().clone()]))
+        .collect(),
+    )),
+    Var::Set(val) => Ok(array(
+      val
+        .iter()
+        .map(|item| array(vec![Var::from(item), Var::from(item)]))
+        .collect(),
+    )),
+    _ => Err(type_error()),
+  }
+}
+/// 在 `Handler` 中注册 `Map`、`Set` 构造函数及其方法。
+/// 方法按插入顺序遍历，键按值比较（见 `VarKey`）。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let both: &[ValueType] = &[ValueType::Map, ValueType::Set];
+  handler.add_builtin("Map", map_new::<Parser>);
+  handler.add_builtin("Set", set_new::<Parser>);
+  handler.add_native("size", NativeFunc::from((both, size::<Parser> as _, true)));
+  handler.add_native("has", NativeFunc::from((both, has::<Parser> as _, false)));
+  handler.add_native(
+    "get",
+    NativeFunc::from((&[ValueType::Map][..], get::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "set",
+    NativeFunc::from((&[ValueType::Map][..], set::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "add",
+    NativeFunc::from((&[ValueType::Set][..], add::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "delete",
+    NativeFunc::from((both, delete::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "clear",
+    NativeFunc::from((both, clear::<Parser> as _, false)),
+  );
+  handler.add_native("keys", NativeFunc::from((both, keys::<Parser> as _, false)));
+  handler.add_native(
+    "values",
+    NativeFunc::from((both, values::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "entries",
+    NativeFunc::from((both, entries::<Parser> as _, false)),
+  );
+}
+#[cfg(test)]
+mod tests {
+  use super::super::gc;
+  use super::super::testing::{call, handler, method, var};
+  use super::*;
+  #[test]
+  fn clones_share_the_map() {
+    let handler = handler();
+    let map = call(&handler, "Map", vec![]);
+    let copy = map.clone();
+    method(&handler, map.clone(), "set", vec![var("\"a\""), var("1")]);
+    assert_eq!(
+      method(&handler, copy, "get", vec![var("\"a\"")]).to_string(),
+      "1"
+    );
+    let other = map.deep_clone();
+    method(&handler, map.clone(), "clear", vec![]);
+    assert_eq!(method(&handler, other, "size", vec![]).to_string(), "1");
+  }
+  #[test]
+  fn cyclic_maps_compare_print_and_collect() {
+    let handler = handler();
+    let cyclic = || {
+      let map = call(&handler, "Map", vec![]);
+      method(
+        &handler,
+        map.clone(),
+        "set",
+        vec![var("\"self\""), map.clone()],
+      );
+      map
+    };
+    let (left, right) = (cyclic(), cyclic());
+    assert!(matches!(
+      left.clone().opcall("==", &right),
+      Ok(Var::Boolean(true))
+    ));
+    assert_eq!(left.to_string(), "Map([[\"self\",[Circular]]])");
+    drop((left, right));
+    assert_eq!(gc::collect(), 2);
+  }
+}
@@ -0,0 +1,243 @@
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::util::throw;
+use super::var::Var;
+use std::cell::RefCell;
+use std::io::{self, BufRead, Write};
+use std::rc::Rc;
+/// 控制台。`print` 等内置函数通过它读写，默认连接到进程的标准输入、输出与错误。
+/// 宿主可以重定向，例如在测试中捕获输出：
+/// ```
+/// let out = Buffer::new();
+/// handler.console.set_stdout(out.clone());
+/// handler.console.set_stdin(io::Cursor::new("1\n2\n"));
+/// assert_eq!(out.contents(), "");
+/// ```
+pub struct Console {
+  /// 为 `None` 时写入进程的标准输出。
+  stdout: RefCell<Option<Box<dyn Write>>>,
+  /// 为 `None` 时写入进程的标准错误。
+  stderr: RefCell<Option<Box<dyn Write>>>,
+  /// 为 `None` 时从进程的标准输入读取。
+  stdin: RefCell<Option<Box<dyn BufRead>>>,
+}
+impl Default for Console {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl Console {
+  pub fn new() -> Self {
+    Console {
+      stdout: RefCell::new(None),
+      stderr: RefCell::new(None),
+      stdin: RefCell::new(None),
+    }
+  }
+  pub fn set_stdout<W: Write + 'static>(&self, val: W) {
+    *self.stdout.borrow_mut() = Some(Box::new(val));
+  }
+  pub fn set_stderr<W: Write + 'static>(&self, val: W) {
+    *self.stderr.borrow_mut() = Some(Box::new(val));
+  }
+  pub fn set_stdin<R: BufRead + 'static>(&self, val: R) {
+    *self.stdin.borrow_mut() = Some(Box::new(val));
+  }
+  /// 标准输出是否被宿主重定向，而不是进程的标准输出。
+  pub fn stdout_redirected(&self) -> bool {
+    self.stdout.borrow().is_some()
+  }
+  pub fn stderr_redirected(&self) -> bool {
+    self.stderr.borrow().is_some()
+  }
+  pub fn stdin_redirected(&self) -> bool {
+    self.stdin.borrow().is_some()
+  }
+  /// 写入标准输出并立即刷新，使输出与标准错误的顺序一致。
+  pub fn write_out(&self, str: &str) -> io::Result<()> {
+    match &mut *self.stdout.borrow_mut() {
+      Some(out) => write_flush(out, str),
+      None => write_flush(&mut io::stdout(), str),
+    }
+  }
+  pub fn write_err(&self, str: &str) -> io::Result<()> {
+    match &mut *self.stderr.borrow_mut() {
+      Some(err) => write_flush(err, str),
+      None => write_flush(&mut io::stderr(), str),
+    }
+  }
+  /// 读取一行，去掉行尾的 `\n` 或 `\r\n`。已经读到末尾时返回 `None`。
+  pub fn read_line(&self) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let len = match &mut *self.stdin.borrow_mut() {
+      Some(stdin) => stdin.read_line(&mut line)?,
+      None => io::stdin().read_line(&mut line)?,
+    };
+    if len == 0 {
+      return Ok(None);
+    }
+    if line.ends_with('\n') {
+      line.pop();
+      if line.ends_with('\r') {
+        line.pop();
+      }
+    }
+    Ok(Some(line))
+  }
+}
+fn write_flush<W: Write + ?Sized>(out: &mut W, str: &str) -> io::Result<()> {
+  out.write_all(str.as_bytes())?;
+  out.flush()
+}
+/// 共享的内存缓冲区，可以作为 `Console` 的输出，之后取回写入的内容。
+#[derive(Clone, Default)]
+pub struct Buffer(Rc<RefCell<Vec<u8>>>);
+impl Buffer {
+  pub fn new() -> Self {
+    Buffer::default()
+  }
+  /// 写入的内容。不是有效的 UTF-8 的部分以替换字符代替。
+  pub fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.0.borrow()).into_owned()
+  }
+  pub fn clear(&self) {
+    self.0.borrow_mut().clear();
+  }
+}
+impl Write for Buffer {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.borrow_mut().extend_from_slice(buf);
+    Ok(buf.len())
+  }
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+/// 以空格连接参数。顶层的字符串输出其内容，不加引号，其他值使用 `to_string`。
+fn join(args: &[Var]) -> String {
+  args
+    .iter()
+    .map(|item| match item {
+      Var::String(val) => val.clone(),
+      val => val.to_string(),
+    })
+    .collect::<Vec<String>>()
+    .join(" ")
+}
+/// 写入标准输出。纯模式下丢弃写往进程标准输出的内容，不接触宿主的控制台；
+/// 重定向的输出照常写入。
+fn write_out<Parser>(handler: &Handler<Parser>, str: &str) -> Result<Var, LppError> {
+  if handler.permissions.is_pure() && !handler.console.stdout_redirected() {
+    return Ok(Var::Null(()));
+  }
+  match handler.console.write_out(str) {
+    Ok(_) => Ok(Var::Null(())),
+    Err(err) => Err(throw(format!("Failed to write stdout: {}", err))),
+  }
+}
+/// 写入标准错误。与 `write_out` 相同，纯模式下只丢弃写往进程标准错误的内容。
+fn write_err<Parser>(handler: &Handler<Parser>, str: &str) -> Result<Var, LppError> {
+  if handler.permissions.is_pure() && !handler.console.stderr_redirected() {
+    return Ok(Var::Null(()));
+  }
+  match handler.console.write_err(str) {
+    Ok(_) => Ok(Var::Null(())),
+    Err(err) => Err(throw(format!("Failed to write stderr: {}", err))),
+  }
+}
+fn print<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  write_out(handler, &join(&args))
+}
+fn println<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  write_out(handler, &(join(&args) + "\n"))
+}
+fn eprint<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  write_err(handler, &join(&args))
+}
+fn eprintln<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  write_err(handler, &(join(&args) + "\n"))
+}
+/// `readLine(prompt)`。有参数时先把它输出到标准输出作为提示。读到末尾时返回 `null`。
+/// 纯模式下进程的标准输入视为空，总是返回 `null`；重定向的输入照常读取。
+fn read_line<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  if !args.is_empty() {
+    print(handler, None, args)?;
+  }
+  if handler.permissions.is_pure() && !handler.console.stdin_redirected() {
+    return Ok(Var::Null(()));
+  }
+  match handler.console.read_line() {
+    Ok(Some(line)) => Ok(Var::String(line)),
+    Ok(None) => Ok(Var::Null(())),
+    Err(err) => Err(throw(format!("Failed to read stdin: {}", err))),
+  }
+}
+/// 在 `Handler` 中注册 `print`、`println`、`eprint`、`eprintln` 与 `readLine`。
+/// 它们通过 `handler.console` 读写；纯模式下不接触进程的标准输入、输出与错误，
+/// 重定向的部分不受影响。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("print", print::<Parser>);
+  handler.add_builtin("println", println::<Parser>);
+  handler.add_builtin("eprint", eprint::<Parser>);
+  handler.add_builtin("eprintln", eprintln::<Parser>);
+  handler.add_builtin("readLine", read_line::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::super::permission::Permissions;
+  use super::super::testing::{call, handler, var};
+  use super::*;
+  #[test]
+  fn redirected_output_is_captured() {
+    let handler = handler();
+    let (out, err) = (Buffer::new(), Buffer::new());
+    handler.console.set_stdout(out.clone());
+    handler.console.set_stderr(err.clone());
+    handler.console.set_stdin(io::Cursor::new("1\r\n2"));
+    call(&handler, "println", vec![var("\"a\""), var("[1]")]);
+    call(&handler, "eprint", vec![var("\"b\"")]);
+    assert_eq!(
+      call(&handler, "readLine", vec![var("\"> \"")]).to_string(),
+      "\"1\""
+    );
+    assert_eq!(call(&handler, "readLine", vec![]).to_string(), "\"2\"");
+    assert_eq!(call(&handler, "readLine", vec![]).to_string(), "null");
+    assert_eq!(out.contents(), "a [1]\n> ");
+    assert_eq!(err.contents(), "b");
+  }
+  #[test]
+  fn pure_mode_still_writes_to_a_redirect() {
+    let mut handler = handler();
+    handler.permissions = Rc::new(Permissions::pure());
+    let out = Buffer::new();
+    handler.console.set_stdout(out.clone());
+    call(&handler, "print", vec![var("\"captured\"")]);
+    // 标准错误没有重定向，写入被丢弃
+    call(&handler, "eprint", vec![var("\"dropped\"")]);
+    assert_eq!(call(&handler, "readLine", vec![]).to_string(), "null");
+    assert_eq!(out.contents(), "captured");
+    assert!(!handler.console.stderr_redirected());
+  }
+}
@@ -0,0 +1,88 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::permission::Capability;
+use super::symbol::Symbol;
+use super::util::get_string;
+use super::var::Var;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::env;
+use std::rc::Rc;
+/// 变量名不能为空，也不能含有 `=` 与 `\0`，否则 `env::set_var` 会 panic。
+fn get_name(args: &[Var], index: usize) -> Result<String, LppError> {
+  let name = get_string(args, index)?;
+  if name.is_empty() || name.contains('=') || name.contains('\0') {
+    return Err(LppError::Error(Error::from(
+      "Invalid environment variable name",
+    )));
+  }
+  Ok(name)
+}
+/// 变量不存在或不是有效的 Unicode 时返回 `null`。
+fn env_get<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let name = get_name(&args, 0)?;
+  Ok(match env::var(name) {
+    Ok(val) => Var::String(val),
+    Err(_) => Var::Null(()),
+  })
+}
+/// 值为 `null` 时删除变量。
+fn env_set<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let name = get_name(&args, 0)?;
+  let value = match args.get(1) {
+    Some(Var::Null(_)) => None,
+    _ => Some(get_string(&args, 1)?),
+  };
+  if value.as_ref().is_some_and(|val| val.contains('\0')) {
+    return Err(LppError::Error(Error::from(
+      "Invalid environment variable value",
+    )));
+  }
+  match value {
+    Some(value) => env::set_var(name, value),
+    None => env::remove_var(name),
+  }
+  Ok(Var::Null(()))
+}
+/// 以对象返回所有值为有效 Unicode 的环境变量。
+fn env_vars<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  for (key, value) in env::vars_os() {
+    if let (Some(key), Some(value)) = (key.to_str(), value.to_str()) {
+      ret.insert(
+        Symbol::from(key),
+        Rc::new(RefCell::new(Var::String(value.to_string()))),
+      );
+    }
+  }
+  Ok(Var::from(ret))
+}
+/// 在 `Handler` 中注册 `env.get`、`env.set` 与 `env.vars`，都需要 `Capability::Env`。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let funcs: [(&str, BuiltinFunc<Parser>); 3] = [
+    ("env.get", env_get::<Parser>),
+    ("env.set", env_set::<Parser>),
+    ("env.vars", env_vars::<Parser>),
+  ];
+  for (name, func) in funcs {
+    handler.builtin.insert(
+      String::from(name),
+      NativeFunc::from(func).requires(Capability::Env),
+    );
+  }
+}
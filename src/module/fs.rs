@@ -0,0 +1,320 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::permission::{self, Capability};
+use super::symbol::Symbol;
+use super::util::{cell, get_string, throw};
+use super::var::Var;
+use chrono::{DateTime, Utc};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+fn io_error(err: io::Error, path: &str) -> LppError {
+  throw(format!("{}: '{}'", err, path))
+}
+fn get_flag(args: &[Var], index: usize) -> Result<bool, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(false),
+    Some(Var::Boolean(val)) => Ok(*val),
+    Some(_) => Err(LppError::Error(Error::from("Boolean expected"))),
+  }
+}
+/// 取路径参数并检查权限，返回原路径与解析后的路径。
+fn get_path<Parser>(
+  handler: &Handler<Parser>,
+  args: &[Var],
+  write: bool,
+) -> Result<(String, PathBuf), LppError> {
+  let path = get_string(args, 0)?;
+  let real = permission::resolve(Path::new(&path));
+  handler.permissions.check(if write {
+    Capability::Write(real.clone())
+  } else {
+    Capability::Read(real.clone())
+  })?;
+  Ok((path, real))
+}
+fn read_text<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (path, real) = get_path(handler, &args, false)?;
+  match fs::read_to_string(real) {
+    Ok(val) => Ok(Var::String(val)),
+    Err(err) => Err(io_error(err, &path)),
+  }
+}
+/// `fs.writeText(path, text, append)`。`append` 为 `true` 时追加到文件末尾。
+fn write_text<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (path, real) = get_path(handler, &args, true)?;
+  let text = get_string(&args, 1)?;
+  let ret = if get_flag(&args, 2)? {
+    fs::OpenOptions::new()
+      .append(true)
+      .create(true)
+      .open(real)
+      .and_then(|mut file| io::Write::write_all(&mut file, text.as_bytes()))
+  } else {
+    fs::write(real, text)
+  };
+  match ret {
+    Ok(_) => Ok(Var::Null(())),
+    Err(err) => Err(io_error(err, &path)),
+  }
+}
+/// 返回目录中的文件名，按名称排序。
+fn read_dir<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (path, real) = get_path(handler, &args, false)?;
+  let mut names = vec![];
+  for item in fs::read_dir(real).map_err(|err| io_error(err, &path))? {
+    let item = item.map_err(|err| io_error(err, &path))?;
+    names.push(item.file_name().to_string_lossy().into_owned());
+  }
+  names.sort();
+  Ok(Var::from(
+    names
+      .into_iter()
+      .map(|item| cell(Var::String(item)))
+      .collect::<Vec<Rc<RefCell<Var>>>>(),
+  ))
+}
+fn exists<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (_, real) = get_path(handler, &args, false)?;
+  Ok(Var::Boolean(real.exists()))
+}
+/// `fs.mkdir(path, recursive)`。`recursive` 为 `true` 时同时创建不存在的上级目录。
+fn mkdir<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (path, real) = get_path(handler, &args, true)?;
+  let ret = if get_flag(&args, 1)? {
+    fs::create_dir_all(real)
+  } else {
+    fs::create_dir(real)
+  };
+  match ret {
+    Ok(_) => Ok(Var::Null(())),
+    Err(err) => Err(io_error(err, &path)),
+  }
+}
+/// 解析路径，但不跟随最后一级的符号链接，得到链接本身所在的位置。
+fn resolve_entry(path: &Path) -> PathBuf {
+  match (path.parent(), path.file_name()) {
+    (Some(dir), Some(name)) => permission::resolve(dir).join(name),
+    _ => permission::resolve(path),
+  }
+}
+/// `fs.remove(path, recursive)`。删除非空目录时 `recursive` 必须为 `true`。
+/// 删除会修改所在的目录，因此检查的是上级目录的写权限，授权的目录本身不能删除。
+/// 符号链接只删除链接本身，不影响它指向的文件或目录。
+fn remove<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let path = get_string(&args, 0)?;
+  let real = resolve_entry(Path::new(&path));
+  match real.parent() {
+    Some(dir) => handler
+      .permissions
+      .check(Capability::Write(dir.to_path_buf()))?,
+    None => {
+      return Err(throw(format!(
+        "Permission denied: cannot remove '{}'",
+        path
+      )))
+    }
+  }
+  let meta = fs::symlink_metadata(&path).map_err(|err| io_error(err, &path))?;
+  let ret = if !meta.is_dir() {
+    fs::remove_file(&path)
+  } else if get_flag(&args, 1)? {
+    fs::remove_dir_all(&path)
+  } else {
+    fs::remove_dir(&path)
+  };
+  match ret {
+    Ok(_) => Ok(Var::Null(())),
+    Err(err) => Err(io_error(err, &path)),
+  }
+}
+/// 返回 `{type, size, modified, readonly}`，`type` 为 `"file"`、`"dir"` 或 `"other"`。
+fn stat<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (path, real) = get_path(handler, &args, false)?;
+  let meta = fs::metadata(real).map_err(|err| io_error(err, &path))?;
+  let tp = if meta.is_file() {
+    "file"
+  } else if meta.is_dir() {
+    "dir"
+  } else {
+    "other"
+  };
+  let modified = match meta.modified() {
+    Ok(val) => Var::DateTime(DateTime::<Utc>::from(val).fixed_offset()),
+    Err(_) => Var::Null(()),
+  };
+  let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  ret.insert(Symbol::from("type"), cell(Var::String(tp.to_string())));
+  ret.insert(
+    Symbol::from("size"),
+    cell(Var::Integer(i64::try_from(meta.len()).unwrap_or(i64::MAX))),
+  );
+  ret.insert(Symbol::from("modified"), cell(modified));
+  ret.insert(
+    Symbol::from("readonly"),
+    cell(Var::Boolean(meta.permissions().readonly())),
+  );
+  Ok(Var::from(ret))
+}
+/// 在 `Handler` 中注册 `fs.readText`、`fs.writeText` 等文件操作。
+/// 读取需要 `Capability::Read`，写入、创建与删除需要 `Capability::Write`。
+/// 不允许的访问与 I/O 错误都以 `ThrowValue` 抛出原因。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("fs.readText", read_text::<Parser>);
+  handler.add_builtin("fs.writeText", write_text::<Parser>);
+  handler.add_builtin("fs.readDir", read_dir::<Parser>);
+  handler.add_builtin("fs.exists", exists::<Parser>);
+  handler.add_builtin("fs.mkdir", mkdir::<Parser>);
+  handler.add_builtin("fs.remove", remove::<Parser>);
+  handler.add_builtin("fs.stat", stat::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, show, var, Lpp};
+  use super::*;
+  use std::env;
+  /// 每个测试使用独立的临时目录，结束时删除。
+  struct TempDir(PathBuf);
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let dir = env::temp_dir().join(format!("lpp-fs-{}-{}", name, std::process::id()));
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+      TempDir(permission::resolve(&dir))
+    }
+    fn path(&self, name: &str) -> Var {
+      Var::String(self.0.join(name).to_string_lossy().into_owned())
+    }
+  }
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+  fn run(handler: &Handler<Lpp>, name: &str, args: Vec<Var>) -> String {
+    show(handler.call_builtin(name, None, args))
+  }
+  #[test]
+  fn granted_directory_is_usable() {
+    let dir = TempDir::new("grant");
+    let handler = handler();
+    handler.permissions.grant(Capability::Write(dir.0.clone()));
+    let file = dir.path("sub/a.txt");
+    call(
+      &handler,
+      "fs.mkdir",
+      vec![dir.path("sub/deep"), var("true")],
+    );
+    call(&handler, "fs.writeText", vec![file.clone(), var("\"ab\"")]);
+    call(
+      &handler,
+      "fs.writeText",
+      vec![file.clone(), var("\"c\""), var("true")],
+    );
+    assert_eq!(run(&handler, "fs.readText", vec![file.clone()]), "\"abc\"");
+    assert_eq!(
+      run(&handler, "fs.readDir", vec![dir.path("sub")]),
+      "[\"a.txt\",\"deep\"]"
+    );
+    assert_eq!(run(&handler, "fs.exists", vec![dir.path("none")]), "false");
+    let stat = call(&handler, "fs.stat", vec![file]).to_string();
+    assert!(
+      stat.starts_with("{\"type\":\"file\",\"size\":3,"),
+      "{}",
+      stat
+    );
+    assert!(run(&handler, "fs.readText", vec![dir.path("none")]).starts_with("throw: "));
+  }
+  #[test]
+  fn paths_outside_the_grant_are_denied() {
+    let dir = TempDir::new("deny");
+    let mut handler = handler();
+    handler
+      .permissions
+      .grant(Capability::Read(dir.0.join("in")));
+    fs::create_dir(dir.0.join("in")).unwrap();
+    fs::write(dir.0.join("secret"), "x").unwrap();
+    let denied = |path: &Path, access: &str| {
+      format!(
+        "throw: \"Permission denied: {} access to \\'{}\\' is not allowed\"",
+        access,
+        path.display()
+      )
+    };
+    assert_eq!(
+      run(&handler, "fs.readText", vec![dir.path("in/../secret")]),
+      denied(&dir.0.join("secret"), "read")
+    );
+    assert_eq!(
+      run(
+        &handler,
+        "fs.writeText",
+        vec![dir.path("in/a"), var("\"\"")]
+      ),
+      denied(&dir.0.join("in/a"), "write")
+    );
+    assert_eq!(run(&handler, "fs.readDir", vec![dir.path("in")]), "[]");
+    handler.permissions = Rc::new(permission::Permissions::pure());
+    assert!(run(&handler, "fs.exists", vec![dir.path("in")]).contains("pure mode"));
+  }
+  #[cfg(unix)]
+  #[test]
+  fn remove_keeps_roots_and_link_targets() {
+    let dir = TempDir::new("remove");
+    let handler = handler();
+    let root = dir.0.join("root");
+    fs::create_dir(&root).unwrap();
+    fs::write(dir.0.join("target"), "x").unwrap();
+    std::os::unix::fs::symlink(dir.0.join("target"), root.join("link")).unwrap();
+    fs::create_dir(root.join("full")).unwrap();
+    fs::write(root.join("full/a"), "a").unwrap();
+    handler.permissions.grant(Capability::Write(root.clone()));
+    assert!(run(&handler, "fs.readText", vec![dir.path("root/link")]).starts_with("throw: "));
+    call(&handler, "fs.remove", vec![dir.path("root/link")]);
+    assert!(!root.join("link").exists());
+    assert!(dir.0.join("target").exists());
+    assert!(run(&handler, "fs.remove", vec![dir.path("root/full")]).starts_with("throw: "));
+    call(
+      &handler,
+      "fs.remove",
+      vec![dir.path("root/full"), var("true")],
+    );
+    assert!(!root.join("full").exists());
+    assert!(run(&handler, "fs.remove", vec![dir.path("root")]).starts_with("throw: "));
+    assert!(root.exists());
+  }
+}
@@ -0,0 +1,292 @@
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::symbol::Symbol;
+use super::var::{ArrayRef, MapRef, ObjectRef, Var, VarKey};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::{Rc, Weak};
+/// 默认在追踪的容器达到此数量时自动回收。
+const DEFAULT_THRESHOLD: usize = 10000;
+enum Tracked {
+  Array(Weak<RefCell<Vec<Rc<RefCell<Var>>>>>),
+  Object(Weak<RefCell<IndexMap<Symbol, Rc<RefCell<Var>>>>>),
+  Map(Weak<RefCell<IndexMap<VarKey, Rc<RefCell<Var>>>>>),
+}
+enum Node {
+  Array(ArrayRef),
+  Object(ObjectRef),
+  Map(MapRef),
+}
+struct Collector {
+  tracked: Vec<Tracked>,
+  threshold: usize,
+  next: usize,
+}
+thread_local! {
+  static COLLECTOR: RefCell<Collector> = const {
+    RefCell::new(Collector {
+      tracked: vec![],
+      threshold: DEFAULT_THRESHOLD,
+      next: DEFAULT_THRESHOLD,
+    })
+  };
+}
+fn id<T>(val: &Rc<T>) -> usize {
+  Rc::as_ptr(val) as *const () as usize
+}
+impl Node {
+  fn id(&self) -> usize {
+    match self {
+      Node::Array(val) => id(val),
+      Node::Object(val) => id(val),
+      Node::Map(val) => id(val),
+    }
+  }
+  fn strong_count(&self) -> usize {
+    match self {
+      Node::Array(val) => Rc::strong_count(val),
+      Node::Object(val) => Rc::strong_count(val),
+      Node::Map(val) => Rc::strong_count(val),
+    }
+  }
+  /// 列出此容器直接引用的容器。
+  /// `exclusive` 为真时只经过没有被其他地方持有的元素，否则经过所有元素。
+  /// 正被可变借用的容器或元素无法访问，将被跳过。
+  fn children(&self, exclusive: bool, out: &mut Vec<usize>) {
+    match self {
+      Node::Array(val) => {
+        if let Ok(val) = val.try_borrow() {
+          for item in val.iter() {
+            slot_children(item, exclusive, out);
+          }
+        }
+      }
+      Node::Object(val) => {
+        if let Ok(val) = val.try_borrow() {
+          for item in val.values() {
+            slot_children(item, exclusive, out);
+          }
+        }
+      }
+      Node::Map(val) => {
+        if let Ok(val) = val.try_borrow() {
+          for item in val.values() {
+            slot_children(item, exclusive, out);
+          }
+        }
+      }
+    }
+  }
+}
+fn slot_children(slot: &Rc<RefCell<Var>>, exclusive: bool, out: &mut Vec<usize>) {
+  if exclusive && Rc::strong_count(slot) != 1 {
+    return;
+  }
+  if let Ok(val) = slot.try_borrow() {
+    if let Some(id) = val.container_id() {
+      out.push(id);
+    }
+  }
+}
+fn track(item: Tracked) {
+  let due = COLLECTOR.with(|collector| {
+    let mut collector = collector.borrow_mut();
+    collector.tracked.push(item);
+    collector.threshold > 0 && collector.tracked.len() >= collector.next
+  });
+  if due {
+    collect();
+  }
+}
+/// 追踪一个新建的数组。由 `Var::from` 调用。
+pub fn track_array(val: &ArrayRef) {
+  track(Tracked::Array(Rc::downgrade(val)));
+}
+/// 追踪一个新建的对象。由 `Var::from` 调用。
+pub fn track_object(val: &ObjectRef) {
+  track(Tracked::Object(Rc::downgrade(val)));
+}
+/// 追踪一个新建的 `Map`。由 `Var::from` 调用。
+pub fn track_map(val: &MapRef) {
+  track(Tracked::Map(Rc::downgrade(val)));
+}
+/// 设置自动回收的阈值。为 `0` 时不自动回收，只能调用 `collect` 手动回收。
+pub fn set_threshold(val: usize) {
+  COLLECTOR.with(|collector| {
+    let mut collector = collector.borrow_mut();
+    collector.threshold = val;
+    collector.next = val;
+  });
+}
+/// 当前追踪的容器数量（包括已经释放、尚未清理的）。
+pub fn tracked() -> usize {
+  COLLECTOR.with(|collector| collector.borrow().tracked.len())
+}
+/// 回收循环引用的数组、对象与 `Map`，返回回收的容器数量。
+///
+/// 采用试探删除：先从每个容器的引用计数中减去来自其他容器的引用，
+/// 剩余计数大于零的容器被容器之外的地方（作用域、栈上的值等）持有，视为根；
+/// 从根出发无法到达的容器只被彼此引用，清空它们即可打破循环。
+/// ```
+/// let a = Var::from(IndexMap::<Symbol, Rc<RefCell<Var>>>::new());
+/// if let Var::Object(obj) = &a {
+///   obj
+///     .borrow_mut()
+///     .insert(Symbol::from("self"), Rc::new(RefCell::new(a.clone())));
+/// }
+/// drop(a);
+/// assert_eq!(gc::collect(), 1);
+/// ```
+pub fn collect() -> usize {
+  let nodes: Vec<Node> = COLLECTOR.with(|collector| {
+    let mut collector = collector.borrow_mut();
+    let mut nodes = vec![];
+    collector.tracked.retain(|item| match item {
+      Tracked::Array(val) => match val.upgrade() {
+        Some(val) => {
+          nodes.push(Node::Array(val));
+          true
+        }
+        None => false,
+      },
+      Tracked::Object(val) => match val.upgrade() {
+        Some(val) => {
+          nodes.push(Node::Object(val));
+          true
+        }
+        None => false,
+      },
+      Tracked::Map(val) => match val.upgrade() {
+        Some(val) => {
+          nodes.push(Node::Map(val));
+          true
+        }
+        None => false,
+      },
+    });
+    nodes
+  });
+  let index: HashMap<usize, usize> = nodes
+    .iter()
+    .enumerate()
+    .map(|(index, item)| (item.id(), index))
+    .collect();
+  // 减去 `nodes` 自身持有的一次引用
+  let mut refs: Vec<usize> = nodes.iter().map(|item| item.strong_count() - 1).collect();
+  let mut children: Vec<usize> = vec![];
+  for item in nodes.iter() {
+    children.clear();
+    item.children(true, &mut children);
+    for child in children.iter() {
+      if let Some(&child) = index.get(child) {
+        refs[child] = refs[child].saturating_sub(1);
+      }
+    }
+  }
+  let mut alive: HashSet<usize> = HashSet::new();
+  let mut stack: Vec<usize> = (0..nodes.len()).filter(|&item| refs[item] > 0).collect();
+  while let Some(item) = stack.pop() {
+    if alive.insert(item) {
+      children.clear();
+      nodes[item].children(false, &mut children);
+      for child in children.iter() {
+        if let Some(&child) = index.get(child) {
+          stack.push(child);
+        }
+      }
+    }
+  }
+  let mut arrays: Vec<Vec<Rc<RefCell<Var>>>> = vec![];
+  let mut objects: Vec<IndexMap<Symbol, Rc<RefCell<Var>>>> = vec![];
+  let mut maps: Vec<IndexMap<VarKey, Rc<RefCell<Var>>>> = vec![];
+  for (index, item) in nodes.iter().enumerate() {
+    if alive.contains(&index) {
+      continue;
+    }
+    match item {
+      Node::Array(val) => {
+        if let Ok(mut val) = val.try_borrow_mut() {
+          arrays.push(std::mem::take(&mut *val));
+        }
+      }
+      Node::Object(val) => {
+        if let Ok(mut val) = val.try_borrow_mut() {
+          objects.push(std::mem::take(&mut *val));
+        }
+      }
+      Node::Map(val) => {
+        if let Ok(mut val) = val.try_borrow_mut() {
+          maps.push(std::mem::take(&mut *val));
+        }
+      }
+    }
+  }
+  let count = arrays.len() + objects.len() + maps.len();
+  drop(nodes);
+  drop(arrays);
+  drop(objects);
+  drop(maps);
+  COLLECTOR.with(|collector| {
+    let mut collector = collector.borrow_mut();
+    collector.tracked.retain(|item| match item {
+      Tracked::Array(val) => val.strong_count() > 0,
+      Tracked::Object(val) => val.strong_count() > 0,
+      Tracked::Map(val) => val.strong_count() > 0,
+    });
+    collector.next = collector.threshold.max(collector.tracked.len() * 2);
+  });
+  count
+}
+fn gc_collect<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(collect() as i64))
+}
+/// 在 `Handler` 中注册 `gc.collect`。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("gc.collect", gc_collect::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  /// 两个互相引用的对象。
+  fn pair() -> Var {
+    let a = Var::from(IndexMap::<Symbol, Rc<RefCell<Var>>>::new());
+    let b = Var::from(vec![Rc::new(RefCell::new(a.clone()))]);
+    if let Var::Object(obj) = &a {
+      obj
+        .borrow_mut()
+        .insert(Symbol::from("b"), Rc::new(RefCell::new(b)));
+    }
+    a
+  }
+  #[test]
+  fn collects_only_unreachable_cycles() {
+    set_threshold(0);
+    let kept = pair();
+    drop(pair());
+    assert_eq!(tracked(), 4);
+    assert_eq!(collect(), 2);
+    assert_eq!(tracked(), 2);
+    assert_eq!(kept.to_string(), "{\"b\":[[Circular]]}");
+    drop(kept);
+    assert_eq!(collect(), 2);
+    assert_eq!(tracked(), 0);
+  }
+  #[test]
+  fn threshold_triggers_collection() {
+    set_threshold(10);
+    for _ in 0..20 {
+      drop(pair());
+    }
+    assert!(tracked() < 20, "{} containers still tracked", tracked());
+    set_threshold(0);
+    collect();
+    assert_eq!(tracked(), 0);
+  }
+}
@@ -0,0 +1,23 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::var::Var;
+use std::cell::RefCell;
+use std::rc::Rc;
+fn structured_clone<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(val) => Ok(val.deep_clone()),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 在 `Handler` 中注册全局函数 `structuredClone`。
+/// 数组与对象按引用传递，需要独立副本时可调用 `structuredClone` 深复制。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("structuredClone", structured_clone::<Parser>);
+}
@@ -0,0 +1,350 @@
+use super::error::Error;
+use super::json;
+use super::lpp::{CodeSplitInterface, Context, Handler, LppError, ParserInterface};
+use super::symbol::Symbol;
+use super::var::{Var, VarKey};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use std::rc::Rc;
+/// 堆中的一个数组、对象或 `Map`。
+pub struct HeapNode {
+  pub id: usize,
+  pub tp: &'static str,
+  /// 元素或属性的数量。
+  pub size: usize,
+  /// 容器自身占用的字节数（估算），不包括其引用的其他容器。
+  pub bytes: usize,
+  /// 从根出发到达此容器的最短路径，如 `global.config.items[3]`。
+  pub path: String,
+}
+/// 从一个容器指向另一个容器的引用。
+pub struct HeapEdge {
+  pub from: usize,
+  pub to: usize,
+  pub label: String,
+}
+/// 从 `Context` 出发可以到达的所有数组、对象与 `Map`，以及它们之间的引用。
+pub struct HeapGraph {
+  pub nodes: Vec<HeapNode>,
+  pub edges: Vec<HeapEdge>,
+}
+/// 估算一个值在容器中占用的字节数，只计入字符串、字节串等内联数据。
+fn slot_bytes(val: &Var) -> usize {
+  size_of::<Rc<RefCell<Var>>>()
+    + size_of::<RefCell<Var>>()
+    + match val {
+      Var::String(val) => val.len(),
+      Var::Bytes(val) => val.len(),
+      Var::Regex(val) => val.source.len(),
+      _ => 0,
+    }
+}
+fn member(key: &str) -> String {
+  let mut chars = key.chars();
+  let ident = match chars.next() {
+    Some(item) => {
+      (item.is_alphabetic() || item == '_')
+        && chars.all(|item| item.is_alphanumeric() || item == '_')
+    }
+    None => false,
+  };
+  if ident {
+    format!(".{}", key)
+  } else {
+    format!("[{}]", Var::String(key.to_string()))
+  }
+}
+/// 列出值直接引用的容器。
+fn children(val: &Var, label: String, out: &mut Vec<(String, Var)>) {
+  if val.container_id().is_some() {
+    out.push((label, val.clone()));
+  }
+}
+fn dot_quote(str: &str) -> String {
+  let mut ret = String::from("\"");
+  for item in str.chars() {
+    match item {
+      '"' => ret += "\\\"",
+      '\\' => ret += "\\\\",
+      '\n' => ret += "\\n",
+      _ => ret.push(item),
+    }
+  }
+  ret + "\""
+}
+impl HeapGraph {
+  /// 从 `global`、`now` 与 `this` 出发，按广度优先遍历所有可以到达的容器。
+  pub fn snapshot(context: &Context) -> Self {
+    let mut roots: Vec<(String, Var)> = vec![(
+      String::from("global"),
+      context.global.borrow().raw().borrow().clone(),
+    )];
+    if !Rc::ptr_eq(&context.now, &context.global) {
+      roots.push((
+        String::from("now"),
+        context.now.borrow().raw().borrow().clone(),
+      ));
+    }
+    if let Some(this) = context.this.upgrade() {
+      roots.push((String::from("this"), this.borrow().clone()));
+    }
+    let mut graph = HeapGraph {
+      nodes: vec![],
+      edges: vec![],
+    };
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    let mut queue: VecDeque<(Option<usize>, String, String, Var)> = roots
+      .into_iter()
+      .map(|(name, val)| (None, name.clone(), name, val))
+      .collect();
+    while let Some((from, label, path, val)) = queue.pop_front() {
+      let id = match val.container_id() {
+        Some(id) => id,
+        None => continue,
+      };
+      if let Some(&to) = index.get(&id) {
+        if let Some(from) = from {
+          graph.edges.push(HeapEdge { from, to, label });
+        }
+        continue;
+      }
+      let to = graph.nodes.len();
+      index.insert(id, to);
+      if let Some(from) = from {
+        graph.edges.push(HeapEdge { from, to, label });
+      }
+      let mut next: Vec<(String, Var)> = vec![];
+      let (tp, size, bytes) = match &val {
+        Var::Array(arr) => {
+          let arr = arr.borrow();
+          for (index, item) in arr.iter().enumerate() {
+            children(&item.borrow(), format!("[{}]", index), &mut next);
+          }
+          (
+            "Array",
+            arr.len(),
+            size_of::<Vec<Rc<RefCell<Var>>>>()
+              + arr
+                .iter()
+                .map(|item| slot_bytes(&item.borrow()))
+                .sum::<usize>(),
+          )
+        }
+        Var::Object(obj) => {
+          let obj = obj.borrow();
+          for (key, value) in obj.iter() {
+            children(&value.borrow(), member(&key.as_str()), &mut next);
+          }
+          (
+            "Object",
+            obj.len(),
+            size_of::<IndexMap<Symbol, Rc<RefCell<Var>>>>()
+              + obj
+                .iter()
+                .map(|(_, value)| size_of::<Symbol>() + slot_bytes(&value.borrow()))
+                .sum::<usize>(),
+          )
+        }
+        Var::Map(map) => {
+          let map = map.borrow();
+          for (key, value) in map.iter() {
+            children(
+              &value.borrow(),
+              format!(".get({})", Var::from(key)),
+              &mut next,
+            );
+          }
+          (
+            "Map",
+            map.len(),
+            size_of::<IndexMap<VarKey, Rc<RefCell<Var>>>>()
+              + map
+                .iter()
+                .map(|(_, value)| size_of::<VarKey>() + slot_bytes(&value.borrow()))
+                .sum::<usize>(),
+          )
+        }
+        _ => continue,
+      };
+      for (label, item) in next {
+        queue.push_back((Some(to), label.clone(), format!("{}{}", path, label), item));
+      }
+      graph.nodes.push(HeapNode {
+        id: to,
+        tp,
+        size,
+        bytes,
+        path,
+      });
+    }
+    graph
+  }
+  /// 输出 Graphviz DOT 格式。
+  pub fn to_dot(&self) -> String {
+    let mut ret = String::from("digraph heap {\n");
+    for item in self.nodes.iter() {
+      ret += format!(
+        "  n{} [label={}];\n",
+        item.id,
+        dot_quote(&format!(
+          "{}\n{} ({}, {} bytes)",
+          item.path, item.tp, item.size, item.bytes
+        ))
+      )
+      .as_str();
+    }
+    for item in self.edges.iter() {
+      ret += format!(
+        "  n{} -> n{} [label={}];\n",
+        item.from,
+        item.to,
+        dot_quote(&item.label)
+      )
+      .as_str();
+    }
+    ret + "}\n"
+  }
+  /// 输出 JSON 格式：`{"nodes":[...],"edges":[...]}`。
+  pub fn to_json(&self) -> String {
+    let cell = |val: Var| Rc::new(RefCell::new(val));
+    let nodes: Vec<Rc<RefCell<Var>>> = self
+      .nodes
+      .iter()
+      .map(|item| {
+        let mut node: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+        node.insert(Symbol::from("id"), cell(Var::Integer(item.id as i64)));
+        node.insert(Symbol::from("type"), cell(Var::String(item.tp.to_string())));
+        node.insert(Symbol::from("size"), cell(Var::Integer(item.size as i64)));
+        node.insert(Symbol::from("bytes"), cell(Var::Integer(item.bytes as i64)));
+        node.insert(Symbol::from("path"), cell(Var::String(item.path.clone())));
+        cell(Var::from(node))
+      })
+      .collect();
+    let edges: Vec<Rc<RefCell<Var>>> = self
+      .edges
+      .iter()
+      .map(|item| {
+        let mut edge: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+        edge.insert(Symbol::from("from"), cell(Var::Integer(item.from as i64)));
+        edge.insert(Symbol::from("to"), cell(Var::Integer(item.to as i64)));
+        edge.insert(Symbol::from("label"), cell(Var::String(item.label.clone())));
+        cell(Var::from(edge))
+      })
+      .collect();
+    let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+    ret.insert(Symbol::from("nodes"), cell(Var::from(nodes)));
+    ret.insert(Symbol::from("edges"), cell(Var::from(edges)));
+    json::stringify(&Var::from(ret), "").unwrap_or_default()
+  }
+}
+fn heap_dump<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let graph = HeapGraph::snapshot(&handler.context);
+  match args.first() {
+    None | Some(Var::Null(_)) => Ok(Var::String(graph.to_json())),
+    Some(Var::String(val)) if val == "json" => Ok(Var::String(graph.to_json())),
+    Some(Var::String(val)) if val == "dot" => Ok(Var::String(graph.to_dot())),
+    _ => Err(LppError::Error(Error::from(
+      "heap.dump expects \"json\" or \"dot\"",
+    ))),
+  }
+}
+/// REPL 的调试命令 `:heap [json|dot]`，返回要输出的堆快照，格式默认为 JSON。
+/// `line` 不是调试命令时返回 `None`，REPL 应当把它作为脚本执行。
+pub fn debug_command<Parser>(
+  handler: &Handler<Parser>,
+  line: &str,
+) -> Option<Result<String, Error>> {
+  let mut words = line.split_whitespace();
+  if words.next() != Some(":heap") {
+    return None;
+  }
+  let graph = HeapGraph::snapshot(&handler.context);
+  Some(match (words.next(), words.next()) {
+    (None, _) | (Some("json"), None) => Ok(graph.to_json()),
+    (Some("dot"), None) => Ok(graph.to_dot()),
+    _ => Err(Error::from("Usage: :heap [json|dot]")),
+  })
+}
+/// 在 `Handler` 中注册 `heap.dump`，以 JSON 或 DOT 格式输出当前的堆。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("heap.dump", heap_dump::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::super::lpp::{NextVal, Scope};
+  use super::super::testing::Lpp;
+  use super::*;
+  use std::collections::BTreeMap;
+  /// 全局变量 `a` 是一个引用自身的对象，`a.items` 是一个数组。
+  fn cyclic() -> Handler<Lpp> {
+    let handler: Handler<Lpp> = Handler::from((
+      Context::from(Rc::new(RefCell::new(Scope::new()))),
+      BTreeMap::new(),
+      NextVal::new(),
+      BTreeMap::new(),
+    ));
+    let obj = Var::from(IndexMap::<Symbol, Rc<RefCell<Var>>>::new());
+    if let Var::Object(map) = &obj {
+      map
+        .borrow_mut()
+        .insert(Symbol::from("self"), Rc::new(RefCell::new(obj.clone())));
+      map.borrow_mut().insert(
+        Symbol::from("items"),
+        Rc::new(RefCell::new(Var::from(vec![Rc::new(RefCell::new(
+          Var::Integer(1),
+        ))]))),
+      );
+    }
+    handler
+      .context
+      .global
+      .borrow_mut()
+      .set(Symbol::from("a"), (obj, false));
+    handler
+  }
+  #[test]
+  fn dump_cyclic_graph_as_dot() {
+    let handler = cyclic();
+    let dot = debug_command(&handler, ":heap dot").unwrap().unwrap();
+    assert!(dot.starts_with("digraph heap {\n"));
+    assert!(dot.contains("  n0 -> n1 [label=\".a\"];\n"));
+    assert!(dot.contains("  n1 -> n1 [label=\".self\"];\n"));
+    assert!(dot.contains("  n1 -> n2 [label=\".items\"];\n"));
+    assert!(dot.contains("global.a.items\\nArray (1, "));
+  }
+  #[test]
+  fn dump_cyclic_graph_as_json() {
+    let handler = cyclic();
+    let text = debug_command(&handler, ":heap").unwrap().unwrap();
+    assert_eq!(
+      debug_command(&handler, ":heap json").unwrap().unwrap(),
+      text
+    );
+    let graph = json::parse(&text).unwrap();
+    let count = |key: &str| match &graph {
+      Var::Object(obj) => match &*obj.borrow()[&Symbol::from(key)].borrow() {
+        Var::Array(arr) => arr.borrow().len(),
+        _ => panic!("{} is not an array", key),
+      },
+      _ => panic!("not an object"),
+    };
+    assert_eq!((count("nodes"), count("edges")), (3, 3));
+    assert!(text.contains("{\"from\":1,\"to\":1,\"label\":\".self\"}"));
+    assert!(text.contains("\"type\":\"Array\",\"size\":1"));
+  }
+  #[test]
+  fn other_lines_are_not_commands() {
+    let handler = cyclic();
+    assert!(debug_command(&handler, "print(1)").is_none());
+    assert!(debug_command(&handler, ":heap svg").unwrap().is_err());
+  }
+}
@@ -0,0 +1,457 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::number;
+use super::symbol::Symbol;
+use super::time;
+use super::var::Var;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+/// 数组与对象嵌套的最大层数。解析与序列化都是递归的，超过时返回错误而不是耗尽栈。
+pub const MAX_DEPTH: usize = 512;
+/// 严格的 JSON 解析器。
+/// 与 `Var::parse` 不同，此解析器只接受 RFC 8259 规定的语法。
+struct JsonParser {
+  chars: Vec<char>,
+  index: usize,
+  /// 当前所在的数组与对象的层数。
+  depth: usize,
+}
+impl JsonParser {
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.index).copied()
+  }
+  fn next(&mut self) -> Option<char> {
+    let ret = self.peek();
+    self.index += 1;
+    ret
+  }
+  fn skip_space(&mut self) {
+    while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+      self.index += 1;
+    }
+  }
+  fn expect(&mut self, item: char) -> Result<(), Error> {
+    if self.next() == Some(item) {
+      Ok(())
+    } else {
+      Err(Error::new(format!(
+        "Unexpected token in JSON at position {}",
+        self.index - 1
+      )))
+    }
+  }
+  fn keyword(&mut self, word: &str, value: Var) -> Result<Var, Error> {
+    for item in word.chars() {
+      self.expect(item)?;
+    }
+    Ok(value)
+  }
+  fn value(&mut self) -> Result<Var, Error> {
+    self.skip_space();
+    match self.peek() {
+      Some('n') => self.keyword("null", Var::Null(())),
+      Some('t') => self.keyword("true", Var::Boolean(true)),
+      Some('f') => self.keyword("false", Var::Boolean(false)),
+      Some('"') => Ok(Var::String(self.string()?)),
+      Some('[') => self.nested(Self::array),
+      Some('{') => self.nested(Self::object),
+      Some('-' | '0'..='9') => self.number(),
+      Some(_) => Err(Error::new(format!(
+        "Unexpected token in JSON at position {}",
+        self.index
+      ))),
+      None => Err(Error::from("Unexpected end of JSON input")),
+    }
+  }
+  fn nested(&mut self, func: fn(&mut Self) -> Result<Var, Error>) -> Result<Var, Error> {
+    if self.depth >= MAX_DEPTH {
+      return Err(Error::new(format!(
+        "JSON is nested too deeply at position {}",
+        self.index
+      )));
+    }
+    self.depth += 1;
+    let ret = func(self);
+    self.depth -= 1;
+    ret
+  }
+  fn digits(&mut self, temp: &mut String) -> Result<(), Error> {
+    let start = temp.len();
+    while let Some(item @ '0'..='9') = self.peek() {
+      temp.push(item);
+      self.index += 1;
+    }
+    if temp.len() == start {
+      Err(Error::new(format!(
+        "Invalid number in JSON at position {}",
+        self.index
+      )))
+    } else {
+      Ok(())
+    }
+  }
+  fn number(&mut self) -> Result<Var, Error> {
+    let mut temp = String::new();
+    if self.peek() == Some('-') {
+      temp.push('-');
+      self.index += 1;
+    }
+    if self.peek() == Some('0') {
+      temp.push('0');
+      self.index += 1;
+    } else {
+      self.digits(&mut temp)?;
+    }
+    if self.peek() == Some('.') {
+      temp.push('.');
+      self.index += 1;
+      self.digits(&mut temp)?;
+    }
+    if let Some('e' | 'E') = self.peek() {
+      temp.push('e');
+      self.index += 1;
+      if let Some(item @ ('+' | '-')) = self.peek() {
+        temp.push(item);
+        self.index += 1;
+      }
+      self.digits(&mut temp)?;
+    }
+    if !temp.contains('.') && !temp.contains('e') && temp != "-0" {
+      if let Ok(val) = temp.parse::<i64>() {
+        return Ok(Var::Integer(val));
+      }
+    }
+    match temp.parse::<f64>() {
+      Ok(val) => Ok(Var::Number(val)),
+      Err(_) => Err(Error::from("Invalid number in JSON")),
+    }
+  }
+  fn hex4(&mut self) -> Result<u32, Error> {
+    let mut ret: u32 = 0;
+    for _ in 0..4 {
+      match self.next().and_then(|item| item.to_digit(16)) {
+        Some(val) => ret = ret * 16 + val,
+        None => return Err(Error::from("Bad Unicode escape in JSON")),
+      }
+    }
+    Ok(ret)
+  }
+  fn string(&mut self) -> Result<String, Error> {
+    self.expect('"')?;
+    let mut ret = String::new();
+    loop {
+      match self.next() {
+        Some('"') => return Ok(ret),
+        Some('\\') => match self.next() {
+          Some('"') => ret.push('"'),
+          Some('\\') => ret.push('\\'),
+          Some('/') => ret.push('/'),
+          Some('b') => ret.push('\u{8}'),
+          Some('f') => ret.push('\u{c}'),
+          Some('n') => ret.push('\n'),
+          Some('r') => ret.push('\r'),
+          Some('t') => ret.push('\t'),
+          Some('u') => {
+            let high = self.hex4()?;
+            let code = if (0xD800..0xDC00).contains(&high) {
+              if self.next() != Some('\\') || self.next() != Some('u') {
+                return Err(Error::from("Bad Unicode escape in JSON"));
+              }
+              let low = self.hex4()?;
+              if !(0xDC00..0xE000).contains(&low) {
+                return Err(Error::from("Bad Unicode escape in JSON"));
+              }
+              0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+            } else {
+              high
+            };
+            match char::from_u32(code) {
+              Some(val) => ret.push(val),
+              None => return Err(Error::from("Bad Unicode escape in JSON")),
+            }
+          }
+          _ => return Err(Error::from("Bad escaped character in JSON")),
+        },
+        Some(item) if (item as u32) < 0x20 => {
+          return Err(Error::from(
+            "Bad control character in string literal in JSON",
+          ))
+        }
+        Some(item) => ret.push(item),
+        None => return Err(Error::from("Unterminated string in JSON")),
+      }
+    }
+  }
+  fn array(&mut self) -> Result<Var, Error> {
+    self.expect('[')?;
+    let mut ret: Vec<Rc<RefCell<Var>>> = vec![];
+    self.skip_space();
+    if self.peek() == Some(']') {
+      self.index += 1;
+      return Ok(Var::from(ret));
+    }
+    loop {
+      ret.push(Rc::new(RefCell::new(self.value()?)));
+      self.skip_space();
+      match self.next() {
+        Some(',') => continue,
+        Some(']') => return Ok(Var::from(ret)),
+        _ => {
+          return Err(Error::from(
+            "Expected ',' or ']' after array element in JSON",
+          ))
+        }
+      }
+    }
+  }
+  fn object(&mut self) -> Result<Var, Error> {
+    self.expect('{')?;
+    let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+    self.skip_space();
+    if self.peek() == Some('}') {
+      self.index += 1;
+      return Ok(Var::from(ret));
+    }
+    loop {
+      self.skip_space();
+      let key = self.string()?;
+      self.skip_space();
+      self.expect(':')?;
+      ret.insert(Symbol::from(key), Rc::new(RefCell::new(self.value()?)));
+      self.skip_space();
+      match self.next() {
+        Some(',') => continue,
+        Some('}') => return Ok(Var::from(ret)),
+        _ => {
+          return Err(Error::from(
+            "Expected ',' or '}' after property value in JSON",
+          ))
+        }
+      }
+    }
+  }
+}
+/// 以严格 JSON 语法反序列化值。
+/// ```
+/// let a = json::parse("{\"awa\":[1,true,null]}").unwrap();
+/// assert_eq!(a.to_string(), "{\"awa\":[1,true,null]}");
+/// ```
+pub fn parse(str: &str) -> Result<Var, Error> {
+  let mut parser = JsonParser {
+    chars: str.chars().collect(),
+    index: 0,
+    depth: 0,
+  };
+  let ret = parser.value()?;
+  parser.skip_space();
+  if parser.peek().is_some() {
+    return Err(Error::new(format!(
+      "Unexpected token in JSON at position {}",
+      parser.index
+    )));
+  }
+  Ok(ret)
+}
+fn quote(str: &str) -> String {
+  let mut tmp = String::from("\"");
+  for item in str.chars() {
+    match item {
+      '"' => tmp += "\\\"",
+      '\\' => tmp += "\\\\",
+      '\u{8}' => tmp += "\\b",
+      '\u{c}' => tmp += "\\f",
+      '\n' => tmp += "\\n",
+      '\r' => tmp += "\\r",
+      '\t' => tmp += "\\t",
+      _ if (item as u32) < 0x20 => tmp += format!("\\u{:04x}", item as u32).as_str(),
+      _ => tmp.push(item),
+    }
+  }
+  tmp + "\""
+}
+/// `path` 为正在输出的数组与对象，用于发现循环引用。
+fn write(
+  val: &Var,
+  indent: &str,
+  prefix: &str,
+  path: &mut Vec<usize>,
+  out: &mut String,
+) -> Result<(), Error> {
+  if let Some(id) = val.container_id() {
+    if path.contains(&id) {
+      return Err(Error::from("Converting circular structure to JSON"));
+    }
+    if path.len() >= MAX_DEPTH {
+      return Err(Error::from("Value is nested too deeply for JSON"));
+    }
+    path.push(id);
+    let ret = write_inner(val, indent, prefix, path, out);
+    path.pop();
+    return ret;
+  }
+  write_inner(val, indent, prefix, path, out)
+}
+fn write_inner(
+  val: &Var,
+  indent: &str,
+  prefix: &str,
+  path: &mut Vec<usize>,
+  out: &mut String,
+) -> Result<(), Error> {
+  let inner = format!("{}{}", prefix, indent);
+  let (open, close) = if indent.is_empty() {
+    (String::new(), String::new())
+  } else {
+    (format!("\n{}", inner), format!("\n{}", prefix))
+  };
+  match val {
+    Var::Null(_) => *out += "null",
+    Var::Boolean(val) => *out += if *val { "true" } else { "false" },
+    Var::Number(val) => {
+      if !val.is_finite() {
+        return Err(Error::from("Number is not representable in JSON"));
+      }
+      *out += number::to_string(*val).as_str();
+    }
+    Var::Integer(val) => *out += val.to_string().as_str(),
+    // JSON 的数值在多数实现中按 `f64` 读取，大整数与十进制小数写成字符串以免丢失精度
+    Var::BigInt(val) => *out += quote(&val.to_string()).as_str(),
+    Var::Decimal(val) => *out += quote(&val.to_string()).as_str(),
+    Var::String(val) => *out += quote(val).as_str(),
+    Var::DateTime(val) => *out += quote(time::format(val, None)?.as_str()).as_str(),
+    Var::Array(val) => {
+      let val = val.borrow();
+      if val.is_empty() {
+        *out += "[]";
+        return Ok(());
+      }
+      out.push('[');
+      for (index, item) in val.iter().enumerate() {
+        if index > 0 {
+          out.push(',');
+        }
+        *out += open.as_str();
+        write(&item.borrow(), indent, inner.as_str(), path, out)?;
+      }
+      *out += close.as_str();
+      out.push(']');
+    }
+    Var::Object(val) => {
+      let val = val.borrow();
+      if val.is_empty() {
+        *out += "{}";
+        return Ok(());
+      }
+      out.push('{');
+      for (index, (key, value)) in val.iter().enumerate() {
+        if index > 0 {
+          out.push(',');
+        }
+        *out += open.as_str();
+        *out += quote(&key.as_str()).as_str();
+        *out += if indent.is_empty() { ":" } else { ": " };
+        write(&value.borrow(), indent, inner.as_str(), path, out)?;
+      }
+      *out += close.as_str();
+      out.push('}');
+    }
+    _ => return Err(Error::from("Value is not serializable to JSON")),
+  }
+  Ok(())
+}
+/// 将值序列化为 JSON。`BigInt` 与 `Decimal` 输出为字符串，`DateTime` 输出为 ISO 8601 字符串。
+/// `indent`: 每一层的缩进。为空字符串时输出紧凑格式。
+/// ```
+/// let a = json::parse("[1,{\"a\":2}]").unwrap();
+/// assert_eq!(json::stringify(&a, "").unwrap(), "[1,{\"a\":2}]");
+/// assert_eq!(json::stringify(&a, "  ").unwrap(), "[\n  1,\n  {\n    \"a\": 2\n  }\n]");
+/// ```
+pub fn stringify(val: &Var, indent: &str) -> Result<String, Error> {
+  let mut out = String::new();
+  write(val, indent, "", &mut vec![], &mut out)?;
+  Ok(out)
+}
+fn json_parse<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  if let Some(Var::String(str)) = args.first() {
+    parse(str.as_str()).map_err(LppError::Error)
+  } else {
+    Err(LppError::Error(Error::from("JSON.parse expects a string")))
+  }
+}
+fn json_stringify<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let indent = match args.get(1) {
+    None | Some(Var::Null(_)) => String::new(),
+    Some(Var::Number(val)) => " ".repeat(val.clamp(0.0, 10.0) as usize),
+    Some(Var::Integer(val)) => " ".repeat((*val).clamp(0, 10) as usize),
+    Some(Var::String(val)) => utf8_slice::slice(val.as_str(), 0, 10).to_string(),
+    Some(_) => return Err(LppError::Error(Error::from("Invalid indentation"))),
+  };
+  match args.first() {
+    Some(val) => Ok(Var::String(
+      stringify(val, indent.as_str()).map_err(LppError::Error)?,
+    )),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 在 `Handler` 中注册 `JSON.parse` 与 `JSON.stringify`。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("JSON.parse", json_parse::<Parser>);
+  handler.add_builtin("JSON.stringify", json_stringify::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn parse_and_stringify_round_trip() {
+    let text = "{\"a\":[1,2.5,\"x\\ny\",true,null],\"b\":{}}";
+    let val = parse(text).unwrap();
+    assert_eq!(stringify(&val, "").unwrap(), text);
+    assert_eq!(
+      stringify(&parse("[1,{\"a\":2}]").unwrap(), "  ").unwrap(),
+      "[\n  1,\n  {\n    \"a\": 2\n  }\n]"
+    );
+    assert!(parse("[1,]").is_err());
+    assert!(parse("{'a':1}").is_err());
+  }
+  #[test]
+  fn parse_rejects_deep_nesting() {
+    let ok = "[".repeat(MAX_DEPTH) + &"]".repeat(MAX_DEPTH);
+    assert!(parse(&ok).is_ok());
+    let deep = "[".repeat(MAX_DEPTH + 1) + &"]".repeat(MAX_DEPTH + 1);
+    assert_eq!(
+      parse(&deep).err().unwrap().err,
+      format!("JSON is nested too deeply at position {}", MAX_DEPTH)
+    );
+    assert!(parse(&"{\"a\":".repeat(100_000)).is_err());
+  }
+  #[test]
+  fn stringify_rejects_deep_nesting() {
+    let mut val = Var::Null(());
+    for _ in 0..MAX_DEPTH {
+      val = Var::from(vec![Rc::new(RefCell::new(val))]);
+    }
+    assert!(stringify(&val, "").is_ok());
+    val = Var::from(vec![Rc::new(RefCell::new(val))]);
+    assert!(stringify(&val, "").is_err());
+  }
+  #[test]
+  fn big_numbers_are_written_as_strings() {
+    let val = Var::parse("[12345678901234567890123n,0.10m,9007199254740993]").unwrap();
+    assert_eq!(
+      stringify(&val, "").unwrap(),
+      "[\"12345678901234567890123\",\"0.10\",9007199254740993]"
+    );
+  }
+}
@@ -1,21 +1,30 @@
+use super::console::Console;
 use super::error::Error;
+use super::math::Random;
 use super::parse::{transfer, LppStatus, QuoteStatus};
+use super::permission::{Capability, Permissions};
+use super::symbol::Symbol;
+use super::time::{Clock, SystemClock};
 use super::var::{covered_with, ExprValue, FuncValue, ValueType, Var};
+use super::{
+  array, bytes, collection, console, env, fs, gc, global, heap, json, math, number, object,
+  pattern, process, string, time,
+};
+use indexmap::IndexMap;
 use std::cell::RefCell;
 use std::collections::{BTreeMap, BTreeSet};
-use std::ptr;
 use std::rc::{Rc, Weak};
 pub struct Scope {
   val: Rc<RefCell<Var>>,
-  constant: BTreeMap<String, bool>,
+  constant: BTreeMap<Symbol, bool>,
 }
 impl Scope {
   pub fn raw(&self) -> Rc<RefCell<Var>> {
     self.val.clone()
   }
-  pub fn get(&self, key: &String) -> (Option<Rc<RefCell<Var>>>, bool) {
+  pub fn get(&self, key: &Symbol) -> (Option<Rc<RefCell<Var>>>, bool) {
     if let Var::Object(ref val) = *self.val.borrow() {
-      if let Some(value) = val.get(key) {
+      if let Some(value) = val.borrow().get(key) {
         if let Some(constant) = self.constant.get(key) {
           (Some(value.clone()), *constant)
         } else {
@@ -28,41 +37,46 @@ impl Scope {
       panic!("self.val must be Var::Object");
     }
   }
-  pub fn set(&mut self, key: String, value: (Var, bool)) {
-    if let Var::Object(ref mut val) = *self.val.borrow_mut() {
-      val.insert(key.clone(), Rc::new(RefCell::new(value.0)));
+  pub fn set(&mut self, key: Symbol, value: (Var, bool)) {
+    if let Var::Object(ref val) = *self.val.borrow() {
+      val
+        .borrow_mut()
+        .insert(key.clone(), Rc::new(RefCell::new(value.0)));
       self.constant.insert(key, value.1);
     } else {
       panic!("self.val must be Var::Object");
     }
   }
-  pub fn remove(&mut self, key: &String) -> (Option<Rc<RefCell<Var>>>, bool) {
-    if let Var::Object(ref mut val) = *self.val.borrow_mut() {
+  pub fn remove(&mut self, key: &Symbol) -> (Option<Rc<RefCell<Var>>>, bool) {
+    if let Var::Object(ref val) = *self.val.borrow() {
       (
-        val.remove(key),
-        if let Some(item) = self.constant.remove(key) {
-          item
-        } else {
-          false
-        },
+        val.borrow_mut().shift_remove(key),
+        self.constant.remove(key).unwrap_or_default(),
       )
     } else {
       panic!("self.val must be Var::Object");
     }
   }
 }
-impl From<(BTreeMap<String, Rc<RefCell<Var>>>, BTreeMap<String, bool>)> for Scope {
-  fn from(val: (BTreeMap<String, Rc<RefCell<Var>>>, BTreeMap<String, bool>)) -> Self {
+impl From<(IndexMap<Symbol, Rc<RefCell<Var>>>, BTreeMap<Symbol, bool>)> for Scope {
+  fn from(val: (IndexMap<Symbol, Rc<RefCell<Var>>>, BTreeMap<Symbol, bool>)) -> Self {
     Scope {
-      val: Rc::new(RefCell::new(Var::Object(val.0))),
+      val: Rc::new(RefCell::new(Var::from(val.0))),
       constant: val.1,
     }
   }
 }
+impl Default for Scope {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl Scope {
   pub fn new() -> Self {
     Scope {
-      val: Rc::new(RefCell::new(Var::Object(BTreeMap::new()))),
+      val: Rc::new(RefCell::new(Var::from(
+        IndexMap::<Symbol, Rc<RefCell<Var>>>::new(),
+      ))),
       constant: BTreeMap::new(),
     }
   }
@@ -74,10 +88,21 @@ pub struct Context {
 }
 impl From<Rc<RefCell<Scope>>> for Context {
   fn from(now: Rc<RefCell<Scope>>) -> Self {
+    let this = Rc::downgrade(&now.borrow().raw());
     Context {
-      now,
       global: now.clone(),
-      this: Rc::downgrade(&now.borrow().raw()),
+      now,
+      this,
+    }
+  }
+}
+impl From<(Rc<RefCell<Scope>>, Rc<RefCell<Scope>>)> for Context {
+  fn from(val: (Rc<RefCell<Scope>>, Rc<RefCell<Scope>>)) -> Self {
+    let this = Rc::downgrade(&val.0.borrow().raw());
+    Context {
+      now: val.0,
+      global: val.1,
+      this,
     }
   }
 }
@@ -103,6 +128,11 @@ pub enum LppError {
   UnexpectedReturn(RetVal),
   Error(Error),
 }
+impl Default for NextVal {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl NextVal {
   pub fn new() -> Self {
     NextVal {
@@ -112,11 +142,68 @@ impl NextVal {
     }
   }
 }
-#[derive(Clone)]
-pub struct NativeFunc {
+pub enum NativeBody<Parser> {
+  Script(FuncValue),
+  Builtin(BuiltinFunc<Parser>),
+}
+impl<Parser> Clone for NativeBody<Parser> {
+  fn clone(&self) -> Self {
+    match self {
+      NativeBody::Script(func) => NativeBody::Script(func.clone()),
+      NativeBody::Builtin(func) => NativeBody::Builtin(*func),
+    }
+  }
+}
+pub struct NativeFunc<Parser> {
   pub use_type: BTreeSet<ValueType>,
-  pub func: FuncValue,
+  pub func: NativeBody<Parser>,
   pub isval: bool,
+  /// 调用前需要检查的能力。
+  pub capability: Option<Capability>,
+}
+impl<Parser> Clone for NativeFunc<Parser> {
+  fn clone(&self) -> Self {
+    NativeFunc {
+      use_type: self.use_type.clone(),
+      func: self.func.clone(),
+      isval: self.isval,
+      capability: self.capability.clone(),
+    }
+  }
+}
+impl<Parser> From<(&[ValueType], BuiltinFunc<Parser>, bool)> for NativeFunc<Parser> {
+  fn from(val: (&[ValueType], BuiltinFunc<Parser>, bool)) -> Self {
+    NativeFunc {
+      use_type: val.0.iter().cloned().collect(),
+      func: NativeBody::Builtin(val.1),
+      isval: val.2,
+      capability: None,
+    }
+  }
+}
+impl<Parser> From<BuiltinFunc<Parser>> for NativeFunc<Parser> {
+  fn from(val: BuiltinFunc<Parser>) -> Self {
+    NativeFunc {
+      use_type: BTreeSet::new(),
+      func: NativeBody::Builtin(val),
+      isval: false,
+      capability: None,
+    }
+  }
+}
+impl<Parser> NativeFunc<Parser> {
+  /// 要求调用者具有能力 `cap`。宿主注册的有副作用的函数应当通过它接受权限检查：
+  /// ```
+  /// handler.add_native(
+  ///   "send",
+  ///   NativeFunc::from((&[ValueType::String][..], send as _, false))
+  ///     .requires(Capability::Host(String::from("net"))),
+  /// );
+  /// ```
+  pub fn requires(mut self, cap: Capability) -> Self {
+    self.capability = Some(cap);
+    self
+  }
 }
 pub trait ParserInterface {
   fn name(&self) -> &String;
@@ -128,44 +215,48 @@ pub trait ParserInterface {
 pub trait CodeSplitInterface {
   fn code_split(str: &str) -> Vec<String>;
 }
+/// 语句的实现。
+pub type CmdFunc<Parser> = fn(parser: &Parser) -> Result<Var, LppError>;
+pub type BuiltinFunc<Parser> = fn(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>;
 pub struct Handler<Parser> {
   pub context: Context,
-  pub cmd: BTreeMap<String, fn(parser: &Parser) -> Result<Var, LppError>>,
+  pub cmd: BTreeMap<Symbol, CmdFunc<Parser>>,
   pub next: RefCell<NextVal>,
-  pub native: BTreeMap<String, NativeFunc>,
+  pub native: BTreeMap<Symbol, Vec<NativeFunc<Parser>>>,
+  pub builtin: BTreeMap<String, NativeFunc<Parser>>,
+  pub clock: Rc<dyn Clock>,
+  pub random: Rc<Random>,
+  /// 宿主授予脚本的能力，默认不授予任何能力。
+  pub permissions: Rc<Permissions>,
+  /// `print`、`readLine` 等读写的控制台，可以重定向。
+  pub console: Rc<Console>,
 }
+#[derive(Clone)]
 pub enum LazyRef {
   Value(Weak<RefCell<Var>>),
   Array((Weak<RefCell<Var>>, usize)),
-  Object((Weak<RefCell<Var>>, String)),
-  ScopeVar((Weak<RefCell<Scope>>, String)),
+  Object((Weak<RefCell<Var>>, Symbol)),
+  ScopeVar((Weak<RefCell<Scope>>, Symbol)),
   Scope(Weak<RefCell<Scope>>),
 }
 impl LazyRef {
-  pub fn get_mut(&mut self) -> Option<Rc<RefCell<Var>>> {
-    match self {
-      LazyRef::Value(val) => {
-        let tmp = val.upgrade();
-        if let Some(rc) = tmp {
-          *self = LazyRef::Value(rc.clone());
-          Some(rc)
-        } else {
-          None
-        }
-      }
+  /// 取得用于写入的值，不存在时自动创建。
+  /// 向已冻结或已密封的对象写入不允许的属性时返回错误。
+  pub fn get_mut(&mut self) -> Result<Option<Rc<RefCell<Var>>>, LppError> {
+    Ok(match self {
+      LazyRef::Value(val) => val.upgrade(),
       LazyRef::Array((val, index)) => {
         if let Some(ptr) = val.upgrade() {
           if let Var::Array(arr) = &*ptr.borrow() {
+            let mut arr = arr.borrow_mut();
             if *index >= arr.len() {
-              if let Var::Array(ref mut arr) = *ptr.borrow_mut() {
-                arr.resize(*index + 1, Rc::new(RefCell::new(Var::new())));
-              }
-            }
-            if let Some(value) = arr.get(*index) {
-              Some(value.clone())
-            } else {
-              None
+              arr.resize_with(*index + 1, || Rc::new(RefCell::new(Var::new())));
             }
+            arr.get(*index).cloned()
           } else {
             panic!("Cannot create in a non-Array object");
           }
@@ -176,16 +267,12 @@ impl LazyRef {
       LazyRef::Object((val, index)) => {
         if let Some(ptr) = val.upgrade() {
           if let Var::Object(obj) = &*ptr.borrow() {
+            object::check_write(obj, index).map_err(LppError::Error)?;
+            let mut obj = obj.borrow_mut();
             if !obj.contains_key(index) {
-              if let Var::Object(ref mut obj) = *ptr.borrow_mut() {
-                obj.insert(index.clone(), Rc::new(RefCell::new(Var::new())));
-              }
-            }
-            if let Some(value) = obj.get(index) {
-              Some(value.clone())
-            } else {
-              None
+              obj.insert(index.clone(), Rc::new(RefCell::new(Var::new())));
             }
+            obj.get(index).cloned()
           } else {
             panic!("Cannot create in a non-Object object");
           }
@@ -195,72 +282,72 @@ impl LazyRef {
       }
       LazyRef::ScopeVar((val, index)) => {
         if let Some(ptr) = val.upgrade() {
-          if let None = ptr.borrow().get(index).0 {
+          // 先结束 `borrow`，再 `borrow_mut`
+          let missing = ptr.borrow().get(index).0.is_none();
+          if missing {
             ptr.borrow_mut().set(index.clone(), (Var::new(), false));
           }
-          ptr.borrow_mut().get(index).0
+          ptr.borrow().get(index).0
         } else {
           None
         }
       }
       LazyRef::Scope(_) => None,
-    }
+    })
   }
-  pub fn get(&self) -> Weak<RefCell<Var>> {
+  /// 取得用于读取的值，不存在或引用的容器已被释放时返回 `None`。
+  pub fn get(&self) -> Option<Rc<RefCell<Var>>> {
     match self {
-      LazyRef::Value(val) => Some(val.clone()),
+      LazyRef::Value(val) => val.upgrade(),
       LazyRef::Array((val, index)) => {
-        if let Var::Array(arr) = &*val.borrow() {
-          if let Some(rc) = arr.get(*index) {
-            Some(rc.clone())
-          } else {
-            None
-          }
+        let val = val.upgrade()?;
+        let ret = if let Var::Array(arr) = &*val.borrow() {
+          arr.borrow().get(*index).cloned()
         } else {
           panic!("Cannot get in a non-Array object");
-        }
+        };
+        ret
       }
       LazyRef::Object((val, index)) => {
-        if let Var::Object(obj) = &*val.borrow() {
-          if let Some(rc) = obj.get(index) {
-            Some(rc.clone())
-          } else {
-            None
-          }
+        let val = val.upgrade()?;
+        let ret = if let Var::Object(obj) = &*val.borrow() {
+          obj.borrow().get(index).cloned()
         } else {
           panic!("Cannot get in a non-Object object");
-        }
+        };
+        ret
       }
-      LazyRef::ScopeVar((val, index)) => val.borrow().get(index).0,
-      LazyRef::Scope(val) => None,
+      LazyRef::ScopeVar((val, index)) => val.upgrade()?.borrow().get(index).0,
+      LazyRef::Scope(_) => None,
     }
   }
 }
 impl From<Rc<RefCell<Var>>> for LazyRef {
   fn from(val: Rc<RefCell<Var>>) -> Self {
-    LazyRef::Value(val)
+    LazyRef::Value(Rc::downgrade(&val))
   }
 }
 impl From<(Rc<RefCell<Var>>, usize)> for LazyRef {
   fn from(val: (Rc<RefCell<Var>>, usize)) -> Self {
-    LazyRef::Array(val)
+    LazyRef::Array((Rc::downgrade(&val.0), val.1))
   }
 }
-impl From<(Rc<RefCell<Var>>, String)> for LazyRef {
-  fn from(val: (Rc<RefCell<Var>>, String)) -> Self {
-    LazyRef::Object(val)
+impl From<(Rc<RefCell<Var>>, Symbol)> for LazyRef {
+  fn from(val: (Rc<RefCell<Var>>, Symbol)) -> Self {
+    LazyRef::Object((Rc::downgrade(&val.0), val.1))
   }
 }
 impl From<Rc<RefCell<Scope>>> for LazyRef {
   fn from(val: Rc<RefCell<Scope>>) -> Self {
-    LazyRef::Scope(val)
+    LazyRef::Scope(Rc::downgrade(&val))
   }
 }
-impl From<(Rc<RefCell<Scope>>, String)> for LazyRef {
-  fn from(val: (Rc<RefCell<Scope>>, String)) -> Self {
-    LazyRef::ScopeVar(val)
+impl From<(Rc<RefCell<Scope>>, Symbol)> for LazyRef {
+  fn from(val: (Rc<RefCell<Scope>>, Symbol)) -> Self {
+    LazyRef::ScopeVar((Rc::downgrade(&val.0), val.1))
   }
 }
+#[derive(Clone)]
 pub enum RefObj {
   Value(Var),
   Ref(LazyRef),
@@ -283,7 +370,7 @@ where
   Handler<Parser>: CodeSplitInterface,
 {
   pub fn is_keyword(&self, str: &str) -> bool {
-    str != "" && self.cmd.contains_key(&str.to_string())
+    !str.is_empty() && Symbol::lookup(str).is_some_and(|key| self.cmd.contains_key(&key))
   }
   pub fn is_identifier(&self, str: &str) -> bool {
     if utf8_slice::len(str) == 0 || self.is_keyword(str) {
@@ -294,12 +381,12 @@ where
         if index == 1 {
           flag = true;
         }
-        if item >= '0' && item <= '9' {
+        if item.is_ascii_digit() {
           if !flag {
             return false;
           }
-        } else if !((item >= 'a' && item <= 'z')
-          || (item >= 'A' && item <= 'Z')
+        } else if !(item.is_ascii_lowercase()
+          || item.is_ascii_uppercase()
           || item == '_'
           || item == '$')
         {
@@ -318,6 +405,31 @@ impl<Parser: ParserInterface> Handler<Parser>
 where
   Handler<Parser>: CodeSplitInterface,
 {
+  /// 注册标准库中所有模块的内置函数与原生函数。
+  /// 权限不受影响：读写文件、环境变量、子进程与时钟仍然需要宿主授予相应的能力。
+  /// ```
+  /// let handler = Handler::from((context, cmd, NextVal::new(), BTreeMap::new())).with_stdlib();
+  /// ```
+  pub fn with_stdlib(mut self) -> Self {
+    array::register(&mut self);
+    bytes::register(&mut self);
+    collection::register(&mut self);
+    console::register(&mut self);
+    env::register(&mut self);
+    fs::register(&mut self);
+    gc::register(&mut self);
+    global::register(&mut self);
+    heap::register(&mut self);
+    json::register(&mut self);
+    math::register(&mut self);
+    number::register(&mut self);
+    object::register(&mut self);
+    pattern::register(&mut self);
+    process::register(&mut self);
+    string::register(&mut self);
+    time::register(&mut self);
+    self
+  }
   pub fn exec(&self, value: &Parser) -> Result<Var, LppError> {
     let retval: Var;
     if self.is_keyword(value.name().as_str()) {
@@ -329,10 +441,10 @@ where
       }
       retval = self
         .cmd
-        .get(value.name())
+        .get(&Symbol::from(value.name()))
         .expect("Keyword implement not found")(value)?;
-    } else if self.cmd.contains_key("") {
-      if self.next.borrow().cmd != "" && self.next.borrow().limit {
+    } else if Symbol::lookup("").is_some_and(|key| self.cmd.contains_key(&key)) {
+      if !self.next.borrow().cmd.is_empty() && self.next.borrow().limit {
         return Err(LppError::Error(Error::from("Invalid statement")));
       }
       if self.next.borrow().cmd != *value.name() {
@@ -340,77 +452,214 @@ where
       }
       retval = self
         .cmd
-        .get(&String::from(""))
+        .get(&Symbol::from(""))
         .expect("Default implement not found")(value)?;
     } else {
       return Err(LppError::Error(Error::from("Invalid statement")));
     }
     Ok(retval)
   }
+  pub fn add_native(&mut self, name: &str, func: NativeFunc<Parser>) {
+    self
+      .native
+      .entry(Symbol::from(name))
+      .or_default()
+      .push(func);
+  }
+  /// 注册不需要能力的内置函数。需要能力时直接向 `builtin` 插入 `NativeFunc::requires` 的结果。
+  pub fn add_builtin(&mut self, name: &str, func: BuiltinFunc<Parser>) {
+    self
+      .builtin
+      .insert(String::from(name), NativeFunc::from(func));
+  }
+  /// 适用于 `tp` 类型的接收者的原生函数 `name`。
+  fn find_native(&self, name: &str, tp: &ValueType) -> Option<&NativeFunc<Parser>> {
+    Symbol::lookup(name)
+      .and_then(|name| self.native.get(&name))
+      .and_then(|items| {
+        items
+          .iter()
+          .find(|item| item.use_type.is_empty() || item.use_type.contains(tp))
+      })
+  }
+  /// 调用原生函数与内置函数的唯一入口，调用前检查 `capability`。
+  fn invoke(
+    &self,
+    item: &NativeFunc<Parser>,
+    this: Option<Rc<RefCell<Var>>>,
+    args: Vec<Var>,
+  ) -> Result<Var, LppError> {
+    if let Some(cap) = &item.capability {
+      self.permissions.check(cap.clone())?;
+    }
+    match &item.func {
+      NativeBody::Builtin(func) => func(self, this, args),
+      NativeBody::Script(func) => self.runfunc(func, args),
+    }
+  }
+  pub fn call_native(
+    &self,
+    name: &str,
+    this: Rc<RefCell<Var>>,
+    args: Vec<Var>,
+  ) -> Result<Var, LppError> {
+    let tp = this.borrow().tp();
+    match self.find_native(name, &tp) {
+      Some(item) => self.invoke(item, Some(this), args),
+      None => Err(LppError::Error(Error::from("Native function not found"))),
+    }
+  }
+  pub fn call_builtin(
+    &self,
+    name: &str,
+    this: Option<Rc<RefCell<Var>>>,
+    args: Vec<Var>,
+  ) -> Result<Var, LppError> {
+    match self.builtin.get(name) {
+      Some(item) => self.invoke(item, this, args),
+      None => Err(LppError::Error(Error::from("Builtin not found"))),
+    }
+  }
+  /// 调用函数值：`Function` 通过 `runfunc` 调用，`Builtin` 按名称调用内置函数。
+  pub fn call_value(&self, func: &Var, args: Vec<Var>) -> Result<Var, LppError> {
+    match func {
+      Var::Function(func) => self.runfunc(func, args),
+      Var::Builtin(name) => self.call_builtin(name, None, args),
+      _ => Err(LppError::Error(Error::from("Function expected"))),
+    }
+  }
+  /// 在新的作用域中调用 `func`。缺少的参数使用默认值，全部参数另外保存在 `arguments` 中。
   pub fn runfunc(&self, func: &FuncValue, args: Vec<Var>) -> Result<Var, LppError> {
     let mut scope = Scope::new();
     let mut arguments: Vec<Rc<RefCell<Var>>> = vec![];
     let code = Self::code_split(func.value.value.as_str());
     for (index, item) in func.args.iter().enumerate() {
-      if args.len() > index {
-        //let v = self.expr(Var::parse(item.value.as_str()))?;
-        arguments.push(Rc::new(RefCell::new(args[index].clone())));
-        scope.set(item.name.clone(), (args[index].clone(), false));
+      let v = if args.len() > index {
+        args[index].clone()
+      } else if item.value.is_empty() {
+        return Err(LppError::Error(Error::from("Too few arguments given")));
       } else {
-        if item.value == "" {
-          return Err(LppError::Error(Error::from("Too few arguments given")));
-        }
-        let v = self.expr(Var::parse(item.value.as_str()))?;
-        arguments.push(Rc::new(RefCell::new(v.clone())));
-        scope.set(item.name.clone(), (v.clone(), false));
+        Var::parse(item.value.as_str()).map_err(LppError::Error)?
+      };
+      arguments.push(Rc::new(RefCell::new(v.clone())));
+      scope.set(Symbol::from(&item.name), (v, false));
+    }
+    scope.set(Symbol::from("arguments"), (Var::from(arguments), false));
+    let handler = self.child(Context::from((
+      Rc::new(RefCell::new(scope)),
+      self.context.global.clone(),
+    )));
+    for item in code {
+      match handler.exec(&Parser::parse(item.as_str())) {
+        Ok(_) => {}
+        Err(LppError::UnexpectedReturn(RetVal::RetValue(val))) => return Ok(val),
+        Err(err) => return Err(err),
       }
     }
-    scope.set(String::from("arguments"), (Var::Array(arguments), false))
-    //a.set(, value)
+    Ok(Var::Null(()))
   }
-  pub fn get_member(&self, mut obj: RefObj, index: &Var) -> Result<RefObj, LppError> {
+  /// 在上下文 `context` 中执行的 `Handler`，与 `self` 共享时钟、随机数、权限与控制台。
+  fn child(&self, context: Context) -> Handler<Parser> {
+    Handler {
+      context,
+      cmd: self.cmd.clone(),
+      next: RefCell::new(NextVal::new()),
+      native: self.native.clone(),
+      builtin: self.builtin.clone(),
+      clock: self.clock.clone(),
+      random: self.random.clone(),
+      permissions: self.permissions.clone(),
+      console: self.console.clone(),
+    }
+  }
+  /// 取得 `obj` 的成员 `index`。先查找适用于其类型的原生函数：
+  /// `isval` 的原生函数立即调用并返回结果，其他的与接收者一起作为 `Overloaded` 返回，
+  /// 由调用者通过 `call_native` 调用。否则按下标访问数组元素或对象属性。
+  pub fn get_member(&self, obj: RefObj, index: &Var) -> Result<RefObj, LppError> {
     let find_str = if let Var::String(str) = index {
       str.clone()
     } else {
       index.to_string()
     };
+    // 字节串的下标访问 `b[i]`，结果只读
+    if let Var::Integer(pos) = index {
+      let val = match &obj {
+        RefObj::Value(val) => bytes::index(val, *pos),
+        RefObj::Ref(val) => val.get().and_then(|val| bytes::index(&val.borrow(), *pos)),
+        RefObj::Overloaded(_) => None,
+      };
+      if let Some(val) = val {
+        return Ok(RefObj::Value(val));
+      }
+    }
     if find_str == "this" {
-      Ok(obj)
-    } else if let Some((index, item)) = self.native.iter().find(|(index, _)| **index == find_str) {
-      let scope: Scope = Scope::new();
-      if let RefObj::Ref(obj) = obj {
-        if item.use_type.is_empty()
-          || if let Some(tmp) = obj.get() {
-            item.use_type.contains(&tmp.borrow().tp())
-          } else {
-            false
-          }
-        {
-          if item.isval {
-            let a = Handler::<Parser>::from((
-              Context::from((self.context.now, self.context.global)),
-              self.cmd.clone(),
-              NextVal::new(),
-              self.native.clone(),
-            ));
-          }
+      return Ok(obj);
+    }
+    let target = match &obj {
+      RefObj::Value(val) => Rc::new(RefCell::new(val.clone())),
+      RefObj::Ref(val) | RefObj::Overloaded((_, val)) => match val.get() {
+        Some(val) => val,
+        None => {
+          return Err(LppError::Error(Error::from(
+            "Cannot read member of undefined",
+          )))
         }
-      }
+      },
+    };
+    let tp = target.borrow().tp();
+    if let Some(item) = self.find_native(&find_str, &tp) {
+      return if item.isval {
+        Ok(RefObj::Value(self.invoke(item, Some(target), vec![])?))
+      } else {
+        Ok(RefObj::Overloaded((
+          Var::String(find_str),
+          LazyRef::from(target),
+        )))
+      };
     }
-  }
-  fn update_scope(now: Scope, temp: &Scope) -> Scope {
-    if let Var::Object(obj) = &*now.raw().borrow() {
-      for item in obj.keys().cloned().collect::<Vec<String>>() {
-        if let (Some(v), c) = temp.get(&item) {
-          now.set(item, (v.borrow().clone(), c))
+    let owned = matches!(obj, RefObj::Value(_));
+    let val = target.borrow();
+    match (&*val, index) {
+      (Var::Array(arr), Var::Integer(pos)) if *pos >= 0 => {
+        if owned {
+          Ok(RefObj::Value(match arr.borrow().get(*pos as usize) {
+            Some(item) => item.borrow().clone(),
+            None => Var::new(),
+          }))
+        } else {
+          Ok(RefObj::Ref(LazyRef::from((target.clone(), *pos as usize))))
+        }
+      }
+      (Var::Object(obj), _) => {
+        let key = Symbol::from(find_str);
+        if owned {
+          Ok(RefObj::Value(match obj.borrow().get(&key) {
+            Some(item) => item.borrow().clone(),
+            None => Var::new(),
+          }))
         } else {
-          now.remove(&item);
+          Ok(RefObj::Ref(LazyRef::from((target.clone(), key))))
         }
       }
-      now
+      _ => Err(LppError::Error(Error::from("Member not found"))),
+    }
+  }
+  /// 把 `temp` 中的值写回 `now` 中同名的变量，`temp` 中已不存在的变量从 `now` 中删除。
+  pub fn update_scope(mut now: Scope, temp: &Scope) -> Scope {
+    let keys: Vec<Symbol> = if let Var::Object(obj) = &*now.raw().borrow() {
+      obj.borrow().keys().cloned().collect()
     } else {
       panic!("Scope.raw() must be Var::Object")
+    };
+    for item in keys {
+      if let (Some(v), c) = temp.get(&item) {
+        let v = v.borrow().clone();
+        now.set(item, (v, c))
+      } else {
+        now.remove(&item);
+      }
     }
+    now
   }
   fn firstname(str: &str) -> String {
     let mut temp = String::new();
@@ -421,7 +670,7 @@ where
       if item == '.' && status.quote == QuoteStatus::None && status.brace == 0 {
         break;
       } else if item == '[' && status.quote == QuoteStatus::None && status.brace == 1 {
-        if temp == "" && (lastchar != ']') {
+        if temp.is_empty() && (lastchar != ']') {
           temp.push(item);
         } else {
           break;
@@ -433,48 +682,51 @@ where
     }
     temp
   }
+  /// 从 `start` 开始依次访问 `access` 中的各级成员。
   fn var_index(&self, access: &str, mut start: ResultObj) -> Result<ResultObj, LppError> {
-    let visit = Self::name_split(access);
-    let this_keep = if let RefObj::Value(_) = start.val() {
-      false
-    } else {
-      if let RefObj::Ref(v) = start.val() {
-        if let Some(s) = v.get() {
-          ptr::eq(s.as_ptr(), self.context.this().as_ptr())
-        } else {
-          false
-        }
-      } else if let RefObj::Overloaded(v) = start.val {
-        if let Some(s) = v.1.get() {
-          std::ptr::eq(s.as_ptr(), self.context.this().as_ptr())
-        } else {
-          false
-        }
-      } else {
-        false
-      }
-    };
-    Err(LppError::Error(Error::from("")))
+    for item in Self::name_split(access) {
+      let index = Var::parse(item.as_str()).map_err(LppError::Error)?;
+      let next = self.get_member(start.val.clone(), &index)?;
+      start.pr = std::mem::replace(&mut start.val, next);
+    }
+    Ok(start)
   }
-  fn get_object(&self, str: &str) -> Result<ResultObj, LppError> {
+  /// 按名称取得变量及其成员。变量先在当前作用域中查找，再在全局作用域中查找，
+  /// 都不存在时指向当前作用域，写入时创建。
+  pub fn get_object(&self, str: &str) -> Result<ResultObj, LppError> {
     let first_name = Self::firstname(str);
-    if first_name == "" {
-      return Err(LppError::Error(Error::from("Syntax error")));
-    }
+    let rest = utf8_slice::slice(
+      str,
+      utf8_slice::len(first_name.as_str()),
+      utf8_slice::len(str),
+    );
     if first_name == "this" {
-      self.var_index(
-        utf8_slice::slice(
-          str,
-          utf8_slice::len(first_name.as_str()),
-          utf8_slice::len(str),
-        ),
+      return self.var_index(
+        rest,
         ResultObj {
-          val: RefObj::Ref(LazyRef::Value(self.context.this())),
-          pr: RefObj::Ref(LazyRef::Value(self.context.this())),
+          val: RefObj::Ref(LazyRef::Value(self.context.this.clone())),
+          pr: RefObj::Ref(LazyRef::Value(self.context.this.clone())),
         },
       );
     }
-    Err(LppError::Error(Error::from("Syntax error")))
+    if !self.is_identifier(first_name.as_str()) {
+      return Err(LppError::Error(Error::from("Syntax error")));
+    }
+    let key = Symbol::from(first_name);
+    let scope = if self.context.now.borrow().get(&key).0.is_none()
+      && self.context.global.borrow().get(&key).0.is_some()
+    {
+      self.context.global.clone()
+    } else {
+      self.context.now.clone()
+    };
+    self.var_index(
+      rest,
+      ResultObj {
+        val: RefObj::Ref(LazyRef::from((scope.clone(), key))),
+        pr: RefObj::Ref(LazyRef::from(scope)),
+      },
+    )
   }
   fn name_split(str: &str) -> Vec<String> {
     let mut ret: Vec<String> = vec![];
@@ -491,7 +743,7 @@ where
         && status.quote == QuoteStatus::None
         && status.brace == if item == '[' { 1 } else { 0 }
       {
-        if temp != "" {
+        if !temp.is_empty() {
           ret.push(temp);
           temp = String::new();
         }
@@ -509,26 +761,26 @@ where
         temp.push(item);
       }
     }
-    if temp != "" {
+    if !temp.is_empty() {
       ret.push(temp);
     }
-    return ret;
+    ret
   }
 }
 impl<Parser: ParserInterface>
   From<(
     Context,
-    BTreeMap<String, fn(parser: &Parser) -> Result<Var, LppError>>,
+    BTreeMap<Symbol, CmdFunc<Parser>>,
     NextVal,
-    BTreeMap<String, NativeFunc>,
+    BTreeMap<Symbol, Vec<NativeFunc<Parser>>>,
   )> for Handler<Parser>
 {
   fn from(
     val: (
       Context,
-      BTreeMap<String, fn(parser: &Parser) -> Result<Var, LppError>>,
+      BTreeMap<Symbol, CmdFunc<Parser>>,
       NextVal,
-      BTreeMap<String, NativeFunc>,
+      BTreeMap<Symbol, Vec<NativeFunc<Parser>>>,
     ),
   ) -> Self {
     Handler {
@@ -536,6 +788,11 @@ impl<Parser: ParserInterface>
       cmd: val.1,
       next: RefCell::new(val.2),
       native: val.3,
+      builtin: BTreeMap::new(),
+      clock: Rc::new(SystemClock),
+      random: Rc::new(Random::new()),
+      permissions: Rc::new(Permissions::new()),
+      console: Rc::new(Console::new()),
     }
   }
 }
@@ -549,3 +806,61 @@ impl<Parser: ParserInterface>
 //     }
 //   }
 // }
+#[cfg(test)]
+mod tests {
+  use super::super::permission::Capability;
+  use super::super::testing::{call, handler, method, show, var, Lpp};
+  use super::*;
+  fn secret(_: &Handler<Lpp>, _: Option<Rc<RefCell<Var>>>, _: Vec<Var>) -> Result<Var, LppError> {
+    Ok(Var::String(String::from("secret")))
+  }
+  #[test]
+  fn stdlib_registers_every_module() {
+    let handler = handler();
+    for name in [
+      "JSON.parse",
+      "Math.floor",
+      "print",
+      "fs.readText",
+      "Date.now",
+    ] {
+      assert!(handler.builtin.contains_key(name), "{} missing", name);
+    }
+    assert_eq!(
+      call(&handler, "JSON.stringify", vec![var("[1,2]")]).to_string(),
+      "\"[1,2]\""
+    );
+    assert_eq!(
+      method(&handler, var("\"abc\""), "toUpperCase", vec![]).to_string(),
+      "\"ABC\""
+    );
+  }
+  #[test]
+  fn capability_is_checked_on_every_path() {
+    let mut handler = handler();
+    let cap = Capability::Host(String::from("secret"));
+    handler.add_native(
+      "secret",
+      NativeFunc::from((&[ValueType::Array][..], secret as _, true)).requires(cap.clone()),
+    );
+    let arr = Rc::new(RefCell::new(var("[1]")));
+    let member = |handler: &Handler<Lpp>| match handler
+      .get_member(RefObj::Ref(LazyRef::from(arr.clone())), &var("\"secret\""))
+    {
+      Ok(RefObj::Value(val)) => Ok(val),
+      Ok(_) => panic!("expected a value"),
+      Err(err) => Err(err),
+    };
+    assert!(show(member(&handler)).starts_with("throw: \"Permission denied"));
+    assert!(show(handler.call_native("secret", arr.clone(), vec![]))
+      .starts_with("throw: \"Permission denied"));
+    assert!(show(handler.call_builtin("env.vars", None, vec![]))
+      .starts_with("throw: \"Permission denied"));
+    handler.permissions.grant(cap);
+    assert_eq!(show(member(&handler)), "\"secret\"");
+    assert_eq!(
+      show(handler.call_native("secret", arr.clone(), vec![])),
+      "\"secret\""
+    );
+  }
+}
@@ -0,0 +1,511 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::object;
+use super::permission::Capability;
+use super::symbol::Symbol;
+use super::var::Var;
+use indexmap::IndexMap;
+use num_bigint::BigInt;
+use num_traits::Signed;
+use rust_decimal::Decimal;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+/// 伪随机数生成器（xoshiro256**）。种子相同时生成的序列相同。
+/// 未设置种子时，`Math.random` 在第一次使用时设置：授予了 `Capability::Clock` 时
+/// 以 `handler.clock` 的当前时间为种子，否则（包括纯模式）使用固定的种子。
+/// 需要可复现的结果时可指定种子：
+/// ```
+/// handler.random.seed(42);
+/// ```
+pub struct Random {
+  state: Cell<Option<[u64; 4]>>,
+}
+impl From<u64> for Random {
+  fn from(seed: u64) -> Self {
+    let ret = Random::new();
+    ret.seed(seed);
+    ret
+  }
+}
+impl Default for Random {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl Random {
+  /// 固定的种子，用于没有设置种子时。
+  pub const DEFAULT_SEED: u64 = 0;
+  /// 创建尚未设置种子的生成器。
+  pub fn new() -> Self {
+    Random {
+      state: Cell::new(None),
+    }
+  }
+  pub fn is_seeded(&self) -> bool {
+    self.state.get().is_some()
+  }
+  /// 重新设置种子。
+  pub fn seed(&self, seed: u64) {
+    // 以 splitmix64 展开种子，保证状态不全为零
+    let mut seed = seed;
+    let mut state = [0; 4];
+    for item in state.iter_mut() {
+      seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+      let mut val = seed;
+      val = (val ^ (val >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+      val = (val ^ (val >> 27)).wrapping_mul(0x94d049bb133111eb);
+      *item = val ^ (val >> 31);
+    }
+    self.state.set(Some(state));
+  }
+  /// 返回下一个数。尚未设置种子时使用 `DEFAULT_SEED`。
+  pub fn next_u64(&self) -> u64 {
+    if !self.is_seeded() {
+      self.seed(Random::DEFAULT_SEED);
+    }
+    let mut s = self.state.get().unwrap_or_default();
+    let ret = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+    let t = s[1] << 17;
+    s[2] ^= s[0];
+    s[3] ^= s[1];
+    s[1] ^= s[2];
+    s[0] ^= s[3];
+    s[2] ^= t;
+    s[3] = s[3].rotate_left(45);
+    self.state.set(Some(s));
+    ret
+  }
+  /// 返回 `[0, 1)` 中均匀分布的数。
+  pub fn next_f64(&self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+fn get_number(args: &[Var], index: usize) -> Result<f64, LppError> {
+  match args.get(index) {
+    Some(val) => val
+      .clone()
+      .try_into()
+      .map_err(|_| LppError::Error(Error::from("Number expected"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+fn unary(args: &[Var], func: fn(f64) -> f64) -> Result<Var, LppError> {
+  Ok(Var::Number(func(get_number(args, 0)?)))
+}
+/// 四舍五入，`.5` 向正无穷方向舍入。
+fn round_f64(val: f64) -> f64 {
+  let floor = val.floor();
+  if val - floor >= 0.5 {
+    floor + 1.0
+  } else {
+    floor
+  }
+}
+/// 取整类函数。整数保持原类型，`Decimal` 保持精度，其余按浮点数计算。
+fn integral(
+  args: &[Var],
+  number: fn(f64) -> f64,
+  decimal: fn(&Decimal) -> Decimal,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(Var::Integer(val)) => Ok(Var::Integer(*val)),
+    Some(Var::BigInt(val)) => Ok(Var::BigInt(val.clone())),
+    Some(Var::Decimal(val)) => Ok(Var::Decimal(decimal(val))),
+    _ => unary(args, number),
+  }
+}
+fn math_floor<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  integral(&args, f64::floor, Decimal::floor)
+}
+fn math_ceil<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  integral(&args, f64::ceil, Decimal::ceil)
+}
+fn math_round<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  integral(&args, round_f64, |val| {
+    let floor = val.floor();
+    if *val - floor >= Decimal::new(5, 1) {
+      floor + Decimal::ONE
+    } else {
+      floor
+    }
+  })
+}
+fn math_trunc<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  integral(&args, f64::trunc, Decimal::trunc)
+}
+fn math_abs<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(Var::Integer(val)) => Ok(match val.checked_abs() {
+      Some(val) => Var::Integer(val),
+      None => Var::BigInt(BigInt::from(*val).abs()),
+    }),
+    Some(Var::BigInt(val)) => Ok(Var::BigInt(val.abs())),
+    Some(Var::Decimal(val)) => Ok(Var::Decimal(val.abs())),
+    _ => unary(&args, f64::abs),
+  }
+}
+fn math_sign<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(Var::Integer(val)) => Ok(Var::Integer(val.signum())),
+    Some(Var::BigInt(val)) => Ok(Var::BigInt(val.signum())),
+    Some(Var::Decimal(val)) => Ok(Var::Decimal(val.signum())),
+    _ => unary(&args, |val| {
+      if val == 0.0 || val.is_nan() {
+        val
+      } else {
+        val.signum()
+      }
+    }),
+  }
+}
+/// `min` 与 `max`。参数全为整数时结果为整数，否则按浮点数比较，任一参数为 NaN 时结果为 NaN。
+fn extremum(args: &[Var], max: bool) -> Result<Var, LppError> {
+  if !args.is_empty() && args.iter().all(|item| matches!(item, Var::Integer(_))) {
+    let items = args.iter().filter_map(|item| match item {
+      Var::Integer(val) => Some(*val),
+      _ => None,
+    });
+    return Ok(Var::Integer(if max {
+      items.max().unwrap_or_default()
+    } else {
+      items.min().unwrap_or_default()
+    }));
+  }
+  let mut ret = if max {
+    f64::NEG_INFINITY
+  } else {
+    f64::INFINITY
+  };
+  for index in 0..args.len() {
+    let val = get_number(args, index)?;
+    if val.is_nan() {
+      return Ok(Var::Number(f64::NAN));
+    }
+    ret = if max { ret.max(val) } else { ret.min(val) };
+  }
+  Ok(Var::Number(ret))
+}
+fn math_min<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  extremum(&args, false)
+}
+fn math_max<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  extremum(&args, true)
+}
+fn math_pow<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  if let (Some(Var::Integer(base)), Some(Var::Integer(exp))) = (args.first(), args.get(1)) {
+    if let Some(val) = u32::try_from(*exp)
+      .ok()
+      .and_then(|exp| base.checked_pow(exp))
+    {
+      return Ok(Var::Integer(val));
+    }
+  }
+  Ok(Var::Number(
+    get_number(&args, 0)?.powf(get_number(&args, 1)?),
+  ))
+}
+fn math_atan2<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Number(
+    get_number(&args, 0)?.atan2(get_number(&args, 1)?),
+  ))
+}
+fn math_hypot<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let mut ret = 0.0;
+  for index in 0..args.len() {
+    ret = f64::hypot(ret, get_number(&args, index)?);
+  }
+  Ok(Var::Number(ret))
+}
+fn math_random<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  if !handler.random.is_seeded() {
+    // 只在已经授予时读取时钟，不询问；纯模式下 `allows` 总是返回 `false`
+    let seed = if handler.permissions.allows(&Capability::Clock) {
+      handler.clock.now().timestamp_micros() as u64
+    } else {
+      Random::DEFAULT_SEED
+    };
+    handler.random.seed(seed);
+  }
+  Ok(Var::Number(handler.random.next_f64()))
+}
+fn math_sqrt<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::sqrt)
+}
+fn math_cbrt<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::cbrt)
+}
+fn math_sin<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::sin)
+}
+fn math_cos<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::cos)
+}
+fn math_tan<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::tan)
+}
+fn math_asin<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::asin)
+}
+fn math_acos<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::acos)
+}
+fn math_atan<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::atan)
+}
+fn math_sinh<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::sinh)
+}
+fn math_cosh<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::cosh)
+}
+fn math_tanh<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::tanh)
+}
+fn math_log<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::ln)
+}
+fn math_log2<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::log2)
+}
+fn math_log10<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::log10)
+}
+fn math_exp<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  unary(&args, f64::exp)
+}
+/// 在 `Handler` 中注册 `Math.floor`、`Math.random` 等函数，
+/// 并在全局作用域中定义只读的 `Math` 对象，其中包含这些函数的引用与 `PI`、`E` 等常量。
+/// `Math.random` 使用 `Handler::random`，种子相同时结果可复现。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let funcs: [(&str, BuiltinFunc<Parser>); 26] = [
+    ("floor", math_floor::<Parser>),
+    ("ceil", math_ceil::<Parser>),
+    ("round", math_round::<Parser>),
+    ("trunc", math_trunc::<Parser>),
+    ("abs", math_abs::<Parser>),
+    ("sign", math_sign::<Parser>),
+    ("min", math_min::<Parser>),
+    ("max", math_max::<Parser>),
+    ("pow", math_pow::<Parser>),
+    ("sqrt", math_sqrt::<Parser>),
+    ("cbrt", math_cbrt::<Parser>),
+    ("hypot", math_hypot::<Parser>),
+    ("sin", math_sin::<Parser>),
+    ("cos", math_cos::<Parser>),
+    ("tan", math_tan::<Parser>),
+    ("asin", math_asin::<Parser>),
+    ("acos", math_acos::<Parser>),
+    ("atan", math_atan::<Parser>),
+    ("atan2", math_atan2::<Parser>),
+    ("sinh", math_sinh::<Parser>),
+    ("cosh", math_cosh::<Parser>),
+    ("tanh", math_tanh::<Parser>),
+    ("log", math_log::<Parser>),
+    ("log2", math_log2::<Parser>),
+    ("log10", math_log10::<Parser>),
+    ("exp", math_exp::<Parser>),
+  ];
+  let mut members: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  let random: (&str, BuiltinFunc<Parser>) = ("random", math_random::<Parser>);
+  for (name, func) in funcs.into_iter().chain([random]) {
+    let full = format!("Math.{}", name);
+    handler.add_builtin(&full, func);
+    members.insert(
+      Symbol::from(name),
+      Rc::new(RefCell::new(Var::Builtin(full))),
+    );
+  }
+  for (name, val) in [
+    ("PI", std::f64::consts::PI),
+    ("E", std::f64::consts::E),
+    ("LN2", std::f64::consts::LN_2),
+    ("LN10", std::f64::consts::LN_10),
+    ("LOG2E", std::f64::consts::LOG2_E),
+    ("LOG10E", std::f64::consts::LOG10_E),
+    ("SQRT2", std::f64::consts::SQRT_2),
+    ("SQRT1_2", std::f64::consts::FRAC_1_SQRT_2),
+  ] {
+    members.insert(Symbol::from(name), Rc::new(RefCell::new(Var::Number(val))));
+  }
+  let math = Var::from(members);
+  if let Var::Object(obj) = &math {
+    object::freeze(obj);
+  }
+  handler
+    .context
+    .global
+    .borrow_mut()
+    .set(Symbol::from("Math"), (math, true));
+}
+#[cfg(test)]
+mod tests {
+  use super::super::lpp::RefObj;
+  use super::super::testing::{call, handler, show, var, Lpp};
+  use super::super::var::ValueType;
+  use super::*;
+  fn lookup(handler: &Handler<Lpp>, name: &str) -> Var {
+    match handler.get_object(name).ok().map(|item| item.val().clone()) {
+      Some(RefObj::Ref(val)) => match val.get() {
+        Some(val) => val.borrow().clone(),
+        None => panic!("{} not found", name),
+      },
+      _ => panic!("{} not found", name),
+    }
+  }
+  #[test]
+  fn functions_live_on_the_math_object() {
+    let handler = handler();
+    let keys = call(&handler, "Object.keys", vec![lookup(&handler, "Math")]).to_string();
+    for name in ["\"floor\"", "\"random\"", "\"exp\"", "\"PI\""] {
+      assert!(keys.contains(name), "{} missing from {}", name, keys);
+    }
+    let floor = lookup(&handler, "Math.floor");
+    assert!(floor.tp() == ValueType::Function);
+    assert_eq!(floor.to_string(), "[builtin Math.floor]");
+    assert_eq!(show(handler.call_value(&floor, vec![var("1.5")])), "1.0");
+    assert_eq!(lookup(&handler, "Math.PI").to_string(), "3.141592653589793");
+  }
+  #[test]
+  fn sign_keeps_the_numeric_type() {
+    let handler = handler();
+    let sign = |val: &str| call(&handler, "Math.sign", vec![var(val)]).to_string();
+    let big = Var::BigInt(-BigInt::from(u128::MAX));
+    assert_eq!(call(&handler, "Math.sign", vec![big]).to_string(), "-1n");
+    assert_eq!(sign("12345678901234567890n"), "1n");
+    assert_eq!(sign("2.5m"), "1m");
+    assert_eq!(sign("0m"), "0m");
+    assert_eq!(sign("7"), "1");
+    assert_eq!(sign("0.5"), "1.0");
+  }
+  #[test]
+  fn seeded_random_repeats() {
+    let draw = |seed: u64| {
+      let handler = handler();
+      handler.random.seed(seed);
+      let random = lookup(&handler, "Math.random");
+      (0..5)
+        .map(
+          |_| match handler.call_value(&random, vec![]).ok().unwrap() {
+            Var::Number(val) => val,
+            val => panic!("Number expected, got {}", val),
+          },
+        )
+        .collect::<Vec<f64>>()
+    };
+    let first = draw(42);
+    assert_eq!(first, draw(42));
+    assert_ne!(first, draw(43));
+    assert!(first.iter().all(|val| (0.0..1.0).contains(val)));
+  }
+}
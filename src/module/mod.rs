@@ -1,4 +1,26 @@
+pub mod array;
+pub mod bytes;
+pub mod collection;
+pub mod console;
+pub mod env;
 pub mod error;
+pub mod fs;
+pub mod gc;
+pub mod global;
+pub mod heap;
+pub mod json;
 pub mod lpp;
+pub mod math;
+pub mod number;
+pub mod object;
 pub mod parse;
+pub mod pattern;
+pub mod permission;
+pub mod process;
+pub mod string;
+pub mod symbol;
+#[cfg(test)]
+pub mod testing;
+pub mod time;
+pub mod util;
 pub mod var;
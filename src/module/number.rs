@@ -0,0 +1,664 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::util::receiver;
+use super::var::{ValueType, Var};
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
+use std::cell::RefCell;
+use std::rc::Rc;
+/// 把 `Number` 转换为字符串。所有输出数字的地方（`to_string`、JSON、`format` 等）都使用此函数。
+///
+/// 非有限值输出为 `NaN`、`Infinity` 与 `-Infinity`，`-0` 输出为 `0`；
+/// 绝对值不小于 `1e21` 或小于 `1e-6` 时使用指数形式（如 `1.5e21`、`1e-7`），
+/// 其余使用最短的可精确还原的十进制形式。
+pub fn to_string(val: f64) -> String {
+  if val.is_nan() {
+    String::from("NaN")
+  } else if val.is_infinite() {
+    String::from(if val > 0.0 { "Infinity" } else { "-Infinity" })
+  } else if val == 0.0 {
+    String::from("0")
+  } else if val.abs() >= 1e21 || val.abs() < 1e-6 {
+    format!("{:e}", val)
+  } else {
+    format!("{}", val)
+  }
+}
+/// 十进制数字串。`digits` 为去掉小数点后的全部数字，小数点位于第 `point` 位之前。
+struct Digits {
+  negative: bool,
+  digits: Vec<u8>,
+  point: usize,
+}
+impl From<&str> for Digits {
+  fn from(str: &str) -> Self {
+    let (negative, str) = match str.strip_prefix('-') {
+      Some(str) => (true, str),
+      None => (false, str),
+    };
+    let (int, frac) = str.split_once('.').unwrap_or((str, ""));
+    Digits {
+      negative,
+      digits: int.bytes().chain(frac.bytes()).collect(),
+      point: int.len(),
+    }
+  }
+}
+impl Digits {
+  /// 取得数值的精确十进制展开。`Number` 的每个有限值都可以精确地写成有限位小数。
+  fn exact(val: &Var) -> Option<Digits> {
+    match val {
+      Var::Number(val) if val.is_finite() => {
+        let mut ret = Digits::from(format!("{:.1074}", val.abs()).as_str());
+        ret.negative = *val < 0.0;
+        Some(ret)
+      }
+      Var::Integer(val) => Some(Digits::from(val.to_string().as_str())),
+      Var::BigInt(val) => Some(Digits::from(val.to_string().as_str())),
+      Var::Decimal(val) => Some(Digits::from(val.to_string().as_str())),
+      _ => None,
+    }
+  }
+  /// 只保留前 `len` 位数字，其余位四舍五入（`.5` 远离零）。位数不足时补零。
+  fn round(&mut self, len: usize) {
+    if len >= self.digits.len() {
+      self.digits.resize(len, b'0');
+      return;
+    }
+    let up = self.digits[len] >= b'5';
+    self.digits.truncate(len);
+    if up {
+      let mut index = len;
+      loop {
+        if index == 0 {
+          self.digits.insert(0, b'1');
+          self.point += 1;
+          break;
+        }
+        index -= 1;
+        if self.digits[index] == b'9' {
+          self.digits[index] = b'0';
+        } else {
+          self.digits[index] += 1;
+          break;
+        }
+      }
+    }
+  }
+  fn first_nonzero(&self) -> Option<usize> {
+    self.digits.iter().position(|item| *item != b'0')
+  }
+  fn sign(&self) -> &'static str {
+    if self.negative {
+      "-"
+    } else {
+      ""
+    }
+  }
+  /// 保留 `frac` 位小数的定点形式。
+  fn fixed(mut self, frac: usize) -> String {
+    self.round(self.point + frac);
+    let int = String::from_utf8_lossy(&self.digits[..self.point]);
+    let int = match int.trim_start_matches('0') {
+      "" => "0",
+      int => int,
+    };
+    let mut ret = format!("{}{}", self.sign(), int);
+    if frac > 0 {
+      ret.push('.');
+      ret += &String::from_utf8_lossy(&self.digits[self.point..]);
+    }
+    ret
+  }
+  /// 保留 `len` 位有效数字，返回有效数字与首位数字的指数。
+  fn significant(&mut self, len: usize) -> (String, i64) {
+    match self.first_nonzero() {
+      Some(first) => {
+        self.round(first + len);
+        let first = self.first_nonzero().unwrap_or(0);
+        let mut digits = self.digits[first..].to_vec();
+        digits.resize(len, b'0');
+        (
+          String::from_utf8_lossy(&digits[..len]).to_string(),
+          self.point as i64 - first as i64 - 1,
+        )
+      }
+      None => ("0".repeat(len), 0),
+    }
+  }
+  /// 指数形式，如 `1.50e21`、`2e-7`。
+  fn exponential(mut self, len: usize) -> String {
+    let (digits, exp) = self.significant(len);
+    self.scientific(&digits, exp)
+  }
+  fn scientific(&self, digits: &str, exp: i64) -> String {
+    if digits.len() > 1 {
+      format!("{}{}.{}e{}", self.sign(), &digits[..1], &digits[1..], exp)
+    } else {
+      format!("{}{}e{}", self.sign(), digits, exp)
+    }
+  }
+  /// 保留 `len` 位有效数字，指数小于 `-6` 或不小于 `len` 时使用指数形式。
+  fn precision(mut self, len: usize) -> String {
+    let (digits, exp) = self.significant(len);
+    if exp < -6 || exp >= len as i64 {
+      return self.scientific(&digits, exp);
+    }
+    if exp >= 0 {
+      let int = exp as usize + 1;
+      if int < len {
+        format!("{}{}.{}", self.sign(), &digits[..int], &digits[int..])
+      } else {
+        format!("{}{}", self.sign(), digits)
+      }
+    } else {
+      format!(
+        "{}0.{}{}",
+        self.sign(),
+        "0".repeat((-exp - 1) as usize),
+        digits
+      )
+    }
+  }
+}
+/// 不带类型后缀的数值文本，用于 `format` 与 `join` 等面向显示的场合。
+pub fn plain(val: &Var) -> String {
+  match val {
+    Var::Number(val) => to_string(*val),
+    Var::BigInt(val) => val.to_string(),
+    Var::Decimal(val) => val.to_string(),
+    Var::String(val) => val.clone(),
+    _ => val.to_string(),
+  }
+}
+fn non_finite(val: &Var) -> bool {
+  matches!(val, Var::Number(val) if !val.is_finite())
+}
+/// 保留 `digits` 位小数。`Number` 的绝对值不小于 `1e21` 或非有限时与 `to_string` 相同。
+pub fn to_fixed(val: &Var, digits: usize) -> Result<String, Error> {
+  if let Var::Number(num) = val {
+    if !num.is_finite() || num.abs() >= 1e21 {
+      return Ok(to_string(*num));
+    }
+  }
+  match Digits::exact(val) {
+    Some(item) => Ok(item.fixed(digits)),
+    None => Err(Error::from("Number expected")),
+  }
+}
+/// 保留 `digits` 位有效数字。指数小于 `-6` 或不小于有效位数时使用指数形式。
+pub fn to_precision(val: &Var, digits: usize) -> Result<String, Error> {
+  if non_finite(val) {
+    return Ok(plain(val));
+  }
+  match Digits::exact(val) {
+    Some(item) => Ok(item.precision(digits)),
+    None => Err(Error::from("Number expected")),
+  }
+}
+/// 指数形式。`digits` 为小数点后的位数，为 `None` 时使用最短的形式。
+pub fn to_exponential(val: &Var, digits: Option<usize>) -> Result<String, Error> {
+  if non_finite(val) {
+    return Ok(plain(val));
+  }
+  match digits {
+    Some(digits) => match Digits::exact(val) {
+      Some(item) => Ok(item.exponential(digits + 1)),
+      None => Err(Error::from("Number expected")),
+    },
+    None => {
+      let item = match val {
+        Var::Number(num) => {
+          let mut item = Digits::from(format!("{}", num.abs()).as_str());
+          item.negative = *num < 0.0;
+          item
+        }
+        val => match Digits::exact(val) {
+          Some(item) => item,
+          None => return Err(Error::from("Number expected")),
+        },
+      };
+      let len = match item.first_nonzero() {
+        Some(first) => {
+          let last = item
+            .digits
+            .iter()
+            .rposition(|item| *item != b'0')
+            .unwrap_or(first);
+          last - first + 1
+        }
+        None => 1,
+      };
+      Ok(item.exponential(len))
+    }
+  }
+}
+/// 以 `radix` 进制输出。`Number` 的小数部分最多输出到足以区分不同的值为止。
+pub fn to_radix(val: &Var, radix: u32) -> Result<String, Error> {
+  if !(2..=36).contains(&radix) {
+    return Err(Error::from("Radix must be between 2 and 36"));
+  }
+  if radix == 10 || non_finite(val) {
+    return Ok(plain(val));
+  }
+  match val {
+    Var::Integer(val) => Ok(BigInt::from(*val).to_str_radix(radix)),
+    Var::BigInt(val) => Ok(val.to_str_radix(radix)),
+    Var::Decimal(val) => match TryInto::<f64>::try_into(Var::Decimal(*val)) {
+      Ok(num) => to_radix(&Var::Number(num), radix),
+      Err(err) => Err(err),
+    },
+    Var::Number(num) => {
+      let int = match BigInt::from_f64(num.abs().trunc()) {
+        Some(int) => int,
+        None => return Err(Error::from("Conversion failed")),
+      };
+      let mut ret = format!(
+        "{}{}",
+        if *num < 0.0 { "-" } else { "" },
+        int.to_str_radix(radix)
+      );
+      let mut frac = num.abs().fract();
+      if frac > 0.0 {
+        // 基数为 2 的幂时乘法是精确的，展开必然在有限步内结束
+        let limit = if radix.is_power_of_two() {
+          1100
+        } else {
+          (53.0 / (radix as f64).log2()).ceil() as usize
+        };
+        ret.push('.');
+        for _ in 0..limit {
+          frac *= radix as f64;
+          let digit = frac.trunc() as u32;
+          frac = frac.fract();
+          ret.push(std::char::from_digit(digit, radix).unwrap_or('0'));
+          if frac == 0.0 {
+            break;
+          }
+        }
+      }
+      Ok(ret)
+    }
+    _ => Err(Error::from("Number expected")),
+  }
+}
+/// 格式说明中宽度的上限。
+const MAX_WIDTH: usize = 1000;
+/// 格式说明中精度的上限，与 `toFixed` 相同。
+const MAX_PRECISION: usize = 100;
+/// 格式说明，形如 `[[fill]align][sign][#][0][width][.precision][type]`。
+/// 宽度与精度超过上限时返回错误。
+struct Spec {
+  fill: char,
+  align: Option<char>,
+  sign: Option<char>,
+  alternate: bool,
+  zero: bool,
+  width: usize,
+  precision: Option<usize>,
+  kind: Option<char>,
+}
+impl Spec {
+  fn parse(str: &str) -> Result<Spec, Error> {
+    let chars: Vec<char> = str.chars().collect();
+    let mut spec = Spec {
+      fill: ' ',
+      align: None,
+      sign: None,
+      alternate: false,
+      zero: false,
+      width: 0,
+      precision: None,
+      kind: None,
+    };
+    let mut index = 0;
+    let is_align = |item: Option<&char>| matches!(item, Some('<' | '>' | '^'));
+    if is_align(chars.get(1)) {
+      spec.fill = chars[0];
+      spec.align = Some(chars[1]);
+      index = 2;
+    } else if is_align(chars.first()) {
+      spec.align = Some(chars[0]);
+      index = 1;
+    }
+    if let Some('+' | '-' | ' ') = chars.get(index) {
+      spec.sign = Some(chars[index]);
+      index += 1;
+    }
+    if let Some('#') = chars.get(index) {
+      spec.alternate = true;
+      index += 1;
+    }
+    if let Some('0') = chars.get(index) {
+      spec.zero = true;
+      index += 1;
+    }
+    // 没有数字时返回 `None`，超过 `max` 时返回错误
+    let number = |index: &mut usize, max: usize| -> Result<Option<usize>, Error> {
+      let start = *index;
+      while chars.get(*index).is_some_and(|item| item.is_ascii_digit()) {
+        *index += 1;
+      }
+      if start == *index {
+        return Ok(None);
+      }
+      match chars[start..*index].iter().collect::<String>().parse() {
+        Ok(val) if val <= max => Ok(Some(val)),
+        _ => Err(Error::from("Invalid format spec")),
+      }
+    };
+    spec.width = number(&mut index, MAX_WIDTH)?.unwrap_or(0);
+    if let Some('.') = chars.get(index) {
+      index += 1;
+      spec.precision = match number(&mut index, MAX_PRECISION)? {
+        Some(val) => Some(val),
+        None => return Err(Error::from("Invalid format spec")),
+      };
+    }
+    match chars.get(index) {
+      None => {}
+      Some(item @ ('e' | 'E' | 'x' | 'X' | 'o' | 'b' | 's')) if index + 1 == chars.len() => {
+        spec.kind = Some(*item);
+      }
+      Some(_) => return Err(Error::from("Invalid format spec")),
+    }
+    Ok(spec)
+  }
+  fn apply(&self, val: &Var) -> Result<String, Error> {
+    let numeric = matches!(
+      val,
+      Var::Number(_) | Var::Integer(_) | Var::BigInt(_) | Var::Decimal(_)
+    );
+    let mut body = match (self.kind, numeric) {
+      (Some('s'), _) | (None, false) => {
+        let str = plain(val);
+        match self.precision {
+          Some(len) => str.chars().take(len).collect(),
+          None => str,
+        }
+      }
+      (None, true) => match self.precision {
+        Some(len) => to_fixed(val, len)?,
+        None => plain(val),
+      },
+      (Some(kind @ ('e' | 'E')), true) => {
+        let str = to_exponential(val, self.precision)?;
+        if kind == 'E' {
+          str.to_uppercase()
+        } else {
+          str
+        }
+      }
+      (Some(kind), true) => {
+        let (radix, prefix) = match kind {
+          'x' | 'X' => (16, "0x"),
+          'o' => (8, "0o"),
+          _ => (2, "0b"),
+        };
+        let int = match val {
+          Var::Integer(_) | Var::BigInt(_) => val.clone(),
+          val => match val.clone().convert(ValueType::BigInt) {
+            Ok(val) => val,
+            Err(_) => return Err(Error::from("Integer expected")),
+          },
+        };
+        let str = to_radix(&int, radix)?;
+        let (negative, str) = match str.strip_prefix('-') {
+          Some(str) => (true, str.to_string()),
+          None => (false, str),
+        };
+        let str = if kind == 'X' { str.to_uppercase() } else { str };
+        format!(
+          "{}{}{}",
+          if negative { "-" } else { "" },
+          if self.alternate { prefix } else { "" },
+          str
+        )
+      }
+      (Some(_), false) => return Err(Error::from("Number expected")),
+    };
+    if numeric && !body.starts_with('-') {
+      match self.sign {
+        Some('+') => body.insert(0, '+'),
+        Some(' ') => body.insert(0, ' '),
+        _ => {}
+      }
+    }
+    let len = body.chars().count();
+    if len >= self.width {
+      return Ok(body);
+    }
+    let pad = self.width - len;
+    if numeric && self.zero && self.align.is_none() {
+      // 补零插入到符号与进制前缀之后
+      let mut head = 0;
+      if body.starts_with(['+', '-', ' ']) {
+        head += 1;
+      }
+      if self.alternate && matches!(self.kind, Some('x' | 'X' | 'o' | 'b')) {
+        head += 2;
+      }
+      body.insert_str(head, "0".repeat(pad).as_str());
+      return Ok(body);
+    }
+    let fill = |len: usize| self.fill.to_string().repeat(len);
+    Ok(
+      match self.align.unwrap_or(if numeric { '>' } else { '<' }) {
+        '<' => body + fill(pad).as_str(),
+        '^' => fill(pad / 2) + body.as_str() + fill(pad - pad / 2).as_str(),
+        _ => fill(pad) + body.as_str(),
+      },
+    )
+  }
+}
+/// 按模板格式化。`{}` 依次取参数，`{0}` 取指定位置的参数，`:` 之后为格式说明；
+/// `{{` 与 `}}` 输出花括号本身。
+/// ```
+/// assert_eq!(
+///   format("{0:>8.2}|{1:<4}|{2:#x}", &[Var::Number(3.14259), Var::Integer(7), Var::Integer(255)]).unwrap(),
+///   "    3.14|7   |0xff"
+/// );
+/// ```
+pub fn format(template: &str, args: &[Var]) -> Result<String, Error> {
+  let mut ret = String::new();
+  let mut next = 0;
+  let mut chars = template.chars().peekable();
+  while let Some(item) = chars.next() {
+    match item {
+      '{' if chars.peek() == Some(&'{') => {
+        chars.next();
+        ret.push('{');
+      }
+      '}' if chars.peek() == Some(&'}') => {
+        chars.next();
+        ret.push('}');
+      }
+      '}' => return Err(Error::from("Unmatched '}' in format string")),
+      '{' => {
+        let mut field = String::new();
+        loop {
+          match chars.next() {
+            Some('}') => break,
+            Some(item) => field.push(item),
+            None => return Err(Error::from("Unmatched '{' in format string")),
+          }
+        }
+        let (index, spec) = field.split_once(':').unwrap_or((field.as_str(), ""));
+        let index = if index.is_empty() {
+          next += 1;
+          next - 1
+        } else {
+          match index.trim().parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => return Err(Error::from("Invalid format argument index")),
+          }
+        };
+        match args.get(index) {
+          Some(val) => ret += Spec::parse(spec)?.apply(val)?.as_str(),
+          None => return Err(Error::from("Format argument index out of range")),
+        }
+      }
+      item => ret.push(item),
+    }
+  }
+  Ok(ret)
+}
+/// 读取整数参数，并检查其范围。
+fn get_digits(
+  args: &[Var],
+  index: usize,
+  min: i64,
+  max: i64,
+  name: &str,
+) -> Result<Option<usize>, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(None),
+    Some(val) => match val.clone().convert(ValueType::Integer) {
+      Ok(Var::Integer(val)) if val >= min && val <= max => Ok(Some(val as usize)),
+      _ => Err(LppError::Error(Error::new(format!(
+        "{} argument must be between {} and {}",
+        name, min, max
+      )))),
+    },
+  }
+}
+fn number_to_fixed<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = receiver(this)?.borrow().clone();
+  let digits = get_digits(&args, 0, 0, 100, "toFixed()")?.unwrap_or(0);
+  Ok(Var::String(
+    to_fixed(&val, digits).map_err(LppError::Error)?,
+  ))
+}
+fn number_to_precision<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = receiver(this)?.borrow().clone();
+  match get_digits(&args, 0, 1, 100, "toPrecision()")? {
+    Some(digits) => Ok(Var::String(
+      to_precision(&val, digits).map_err(LppError::Error)?,
+    )),
+    None => Ok(Var::String(plain(&val))),
+  }
+}
+fn number_to_string<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = receiver(this)?.borrow().clone();
+  let radix = get_digits(&args, 0, 2, 36, "toString() radix")?.unwrap_or(10);
+  Ok(Var::String(
+    to_radix(&val, radix as u32).map_err(LppError::Error)?,
+  ))
+}
+fn builtin_format<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let template = match args.first() {
+    Some(Var::String(val)) => val.clone(),
+    Some(_) => return Err(LppError::Error(Error::from("Format string expected"))),
+    None => return Err(LppError::Error(Error::from("Too few arguments given"))),
+  };
+  // `format(template, [a, b])` 与 `format(template, a, b)` 等价
+  let rest: Vec<Var> = match &args[1..] {
+    [Var::Array(val)] => val
+      .borrow()
+      .iter()
+      .map(|item| item.borrow().clone())
+      .collect(),
+    rest => rest.to_vec(),
+  };
+  Ok(Var::String(
+    format(template.as_str(), &rest).map_err(LppError::Error)?,
+  ))
+}
+/// 在 `Handler` 中注册 `format` 以及数值的 `toFixed`、`toPrecision`、`toString(radix)` 方法。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let numeric: &[ValueType] = &[
+    ValueType::Number,
+    ValueType::Integer,
+    ValueType::BigInt,
+    ValueType::Decimal,
+  ];
+  handler.add_builtin("format", builtin_format::<Parser>);
+  handler.add_native(
+    "toFixed",
+    NativeFunc::from((numeric, number_to_fixed::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "toPrecision",
+    NativeFunc::from((numeric, number_to_precision::<Parser> as _, false)),
+  );
+  handler.add_native(
+    "toString",
+    NativeFunc::from((numeric, number_to_string::<Parser> as _, false)),
+  );
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn to_string_uses_exponent_at_both_ends() {
+    assert_eq!(to_string(1e-300), "1e-300");
+    assert_eq!(to_string(1.5e-7), "1.5e-7");
+    assert_eq!(to_string(-1e-7), "-1e-7");
+    assert_eq!(to_string(0.000001), "0.000001");
+    assert_eq!(to_string(0.1), "0.1");
+    assert_eq!(to_string(123456789012345680000.0), "123456789012345680000");
+    assert_eq!(to_string(1.5e21), "1.5e21");
+    assert_eq!(to_string(-0.0), "0");
+    assert_eq!(to_string(f64::NAN), "NaN");
+    assert_eq!(to_string(f64::NEG_INFINITY), "-Infinity");
+  }
+  #[test]
+  fn fixed_precision_and_radix() {
+    assert_eq!(to_fixed(&Var::Number(1.005), 2).unwrap(), "1.00");
+    assert_eq!(to_fixed(&Var::Integer(-3), 2).unwrap(), "-3.00");
+    assert_eq!(to_precision(&Var::Number(123.456), 4).unwrap(), "123.5");
+    assert_eq!(
+      to_precision(&Var::Number(0.00000123), 2).unwrap(),
+      "0.0000012"
+    );
+    assert_eq!(to_exponential(&Var::Number(1500.0), None).unwrap(), "1.5e3");
+    assert_eq!(to_radix(&Var::Integer(255), 16).unwrap(), "ff");
+    assert_eq!(to_radix(&Var::Number(0.5), 2).unwrap(), "0.1");
+    assert!(to_radix(&Var::Integer(1), 37).is_err());
+  }
+  #[test]
+  fn format_template() {
+    assert_eq!(
+      format(
+        "{0:>8.2}|{1:<4}|{2:#x}",
+        &[Var::Number(3.14259), Var::Integer(7), Var::Integer(255)]
+      )
+      .unwrap(),
+      "    3.14|7   |0xff"
+    );
+    assert_eq!(format("{{{}}}", &[Var::Integer(1)]).unwrap(), "{1}");
+    assert!(format("{:.1000}", &[Var::Number(1.0)]).is_err());
+  }
+  #[test]
+  fn digit_arguments_are_range_checked() {
+    let handler = super::super::testing::handler();
+    let ret = handler.call_native(
+      "toFixed",
+      Rc::new(RefCell::new(Var::Number(1.0))),
+      vec![Var::Integer(101)],
+    );
+    assert_eq!(
+      super::super::testing::show(ret),
+      "error: toFixed() argument must be between 0 and 100"
+    );
+  }
+}
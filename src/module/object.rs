@@ -0,0 +1,367 @@
+use super::error::Error;
+use super::lpp::{CodeSplitInterface, Handler, LppError, ParserInterface};
+use super::symbol::Symbol;
+use super::util::{array, cell};
+use super::var::{ObjectRef, Var};
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+/// 对象的完整性级别。
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Integrity {
+  /// 不能添加或删除属性，已有的属性仍可修改。
+  Sealed,
+  /// 不能添加、删除或修改属性。
+  Frozen,
+}
+type IntegrityMap = HashMap<usize, (Weak<RefCell<IndexMap<Symbol, Rc<RefCell<Var>>>>>, Integrity)>;
+thread_local! {
+  /// 以容器地址为键。保存 `Weak` 以便识别已释放的对象，避免地址复用后误判。
+  static INTEGRITY: RefCell<IntegrityMap> =
+    RefCell::new(HashMap::new());
+}
+fn id(obj: &ObjectRef) -> usize {
+  Rc::as_ptr(obj) as *const () as usize
+}
+/// 查询对象的完整性级别，未冻结也未密封时返回 `None`。
+pub fn integrity(obj: &ObjectRef) -> Option<Integrity> {
+  INTEGRITY.with(|table| match table.borrow().get(&id(obj)) {
+    Some((val, level)) if val.strong_count() > 0 => Some(*level),
+    _ => None,
+  })
+}
+fn restrict(obj: &ObjectRef, level: Integrity) {
+  if integrity(obj).is_some_and(|item| item >= level) {
+    return;
+  }
+  INTEGRITY.with(|table| {
+    let mut table = table.borrow_mut();
+    table.retain(|_, (val, _)| val.strong_count() > 0);
+    table.insert(id(obj), (Rc::downgrade(obj), level));
+  });
+}
+/// 密封对象：之后不能添加或删除属性。
+pub fn seal(obj: &ObjectRef) {
+  restrict(obj, Integrity::Sealed);
+}
+/// 冻结对象：之后不能添加、删除或修改属性。冻结是浅层的，不影响属性引用的其他容器。
+pub fn freeze(obj: &ObjectRef) {
+  restrict(obj, Integrity::Frozen);
+}
+/// 检查能否写入属性 `key`（属性不存在时将被创建）。
+pub fn check_write(obj: &ObjectRef, key: &Symbol) -> Result<(), Error> {
+  match integrity(obj) {
+    Some(Integrity::Frozen) => Err(Error::from(
+      format!("Cannot assign to read only property '{}'", key).as_str(),
+    )),
+    Some(Integrity::Sealed) if !obj.borrow().contains_key(key) => Err(Error::from(
+      format!("Cannot add property '{}', object is not extensible", key).as_str(),
+    )),
+    _ => Ok(()),
+  }
+}
+/// 检查能否删除属性 `key`。
+pub fn check_delete(obj: &ObjectRef, key: &Symbol) -> Result<(), Error> {
+  match integrity(obj) {
+    Some(_) => Err(Error::from(
+      format!("Cannot delete property '{}'", key).as_str(),
+    )),
+    None => Ok(()),
+  }
+}
+fn get_object(args: &[Var], index: usize) -> Result<ObjectRef, LppError> {
+  match args.get(index) {
+    Some(Var::Object(val)) => Ok(val.clone()),
+    Some(_) => Err(LppError::Error(Error::from("Object expected"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 把值转换为属性名。字符串直接使用其内容，其他值使用 `to_string` 的结果。
+fn key_of(val: &Var) -> Symbol {
+  match val {
+    Var::String(val) => Symbol::from(val),
+    val => Symbol::from(val.to_string()),
+  }
+}
+fn get_key(args: &[Var], index: usize) -> Result<Symbol, LppError> {
+  match args.get(index) {
+    Some(val) => Ok(key_of(val)),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 写入属性：已有的属性修改其值，不存在时创建。
+fn put(obj: &ObjectRef, key: Symbol, val: Var) -> Result<(), LppError> {
+  check_write(obj, &key).map_err(LppError::Error)?;
+  let slot = obj.borrow().get(&key).cloned();
+  match slot {
+    Some(slot) => *slot.borrow_mut() = val,
+    None => {
+      obj.borrow_mut().insert(key, cell(val));
+    }
+  }
+  Ok(())
+}
+fn object_keys<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  let ret = obj
+    .borrow()
+    .keys()
+    .map(|key| Var::String(key.to_string()))
+    .collect();
+  Ok(array(ret))
+}
+fn object_values<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  let ret = obj
+    .borrow()
+    .values()
+    .map(|value| value.borrow().clone())
+    .collect();
+  Ok(array(ret))
+}
+fn object_entries<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  let ret = obj
+    .borrow()
+    .iter()
+    .map(|(key, value)| array(vec![Var::String(key.to_string()), value.borrow().clone()]))
+    .collect();
+  Ok(array(ret))
+}
+fn object_from_entries<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  match args.first() {
+    Some(Var::Array(val)) => {
+      for item in val.borrow().iter() {
+        match &*item.borrow() {
+          Var::Array(pair) if pair.borrow().len() == 2 => {
+            let pair = pair.borrow();
+            ret.insert(key_of(&pair[0].borrow()), cell(pair[1].borrow().clone()));
+          }
+          _ => {
+            return Err(LppError::Error(Error::from(
+              "Entry must be a [key, value] array",
+            )))
+          }
+        }
+      }
+    }
+    Some(Var::Map(val)) => {
+      for (key, value) in val.borrow().iter() {
+        ret.insert(key_of(&Var::from(key)), cell(value.borrow().clone()));
+      }
+    }
+    Some(_) => return Err(LppError::Error(Error::from("Array or Map expected"))),
+    None => return Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+  Ok(Var::from(ret))
+}
+fn object_assign<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let target = get_object(&args, 0)?;
+  for index in 1..args.len() {
+    if let Var::Null(_) = args[index] {
+      continue;
+    }
+    // 先复制来源的属性，来源与目标可能是同一个对象
+    let items: Vec<(Symbol, Var)> = get_object(&args, index)?
+      .borrow()
+      .iter()
+      .map(|(key, value)| (key.clone(), value.borrow().clone()))
+      .collect();
+    for (key, value) in items {
+      put(&target, key, value)?;
+    }
+  }
+  Ok(Var::Object(target))
+}
+fn object_has_own<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  let ret = match args.get(1) {
+    Some(Var::String(val)) => {
+      Symbol::lookup(val).is_some_and(|key| obj.borrow().contains_key(&key))
+    }
+    _ => obj.borrow().contains_key(&get_key(&args, 1)?),
+  };
+  Ok(Var::Boolean(ret))
+}
+fn object_delete<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  let key = get_key(&args, 1)?;
+  if !obj.borrow().contains_key(&key) {
+    return Ok(Var::Boolean(false));
+  }
+  check_delete(&obj, &key).map_err(LppError::Error)?;
+  let removed = obj.borrow_mut().shift_remove(&key);
+  Ok(Var::Boolean(removed.is_some()))
+}
+fn object_freeze<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  freeze(&obj);
+  Ok(Var::Object(obj))
+}
+fn object_seal<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  seal(&obj);
+  Ok(Var::Object(obj))
+}
+fn object_is_frozen<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  Ok(Var::Boolean(integrity(&obj) == Some(Integrity::Frozen)))
+}
+fn object_is_sealed<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let obj = get_object(&args, 0)?;
+  Ok(Var::Boolean(integrity(&obj).is_some()))
+}
+/// 在 `Handler` 中注册 `Object.keys`、`Object.assign`、`Object.freeze` 等反射函数。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.add_builtin("Object.keys", object_keys::<Parser>);
+  handler.add_builtin("Object.values", object_values::<Parser>);
+  handler.add_builtin("Object.entries", object_entries::<Parser>);
+  handler.add_builtin("Object.fromEntries", object_from_entries::<Parser>);
+  handler.add_builtin("Object.assign", object_assign::<Parser>);
+  handler.add_builtin("Object.hasOwn", object_has_own::<Parser>);
+  handler.add_builtin("Object.delete", object_delete::<Parser>);
+  handler.add_builtin("Object.freeze", object_freeze::<Parser>);
+  handler.add_builtin("Object.seal", object_seal::<Parser>);
+  handler.add_builtin("Object.isFrozen", object_is_frozen::<Parser>);
+  handler.add_builtin("Object.isSealed", object_is_sealed::<Parser>);
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, method, show, var};
+  use super::*;
+  #[test]
+  fn reflection_keeps_insertion_order() {
+    let handler = handler();
+    let obj = var("{\"b\":1,\"a\":[2]}");
+    let run = |name: &str, args: Vec<Var>| call(&handler, name, args).to_string();
+    assert_eq!(run("Object.keys", vec![obj.clone()]), "[\"b\",\"a\"]");
+    assert_eq!(run("Object.values", vec![obj.clone()]), "[1,[2]]");
+    assert_eq!(
+      run("Object.entries", vec![obj.clone()]),
+      "[[\"b\",1],[\"a\",[2]]]"
+    );
+    assert_eq!(
+      run("Object.hasOwn", vec![obj.clone(), var("\"a\"")]),
+      "true"
+    );
+    assert_eq!(
+      run("Object.hasOwn", vec![obj.clone(), var("\"c\"")]),
+      "false"
+    );
+    let copy = run("Object.fromEntries", vec![var("[[\"x\",1],[2,\"y\"]]")]);
+    assert_eq!(copy, "{\"x\":1,\"2\":\"y\"}");
+    let map = call(&handler, "Map", vec![]);
+    method(
+      &handler,
+      map.clone(),
+      "set",
+      vec![var("\"k\""), var("true")],
+    );
+    assert_eq!(run("Object.fromEntries", vec![map]), "{\"k\":true}");
+    let ret = handler.call_builtin("Object.fromEntries", None, vec![var("[[1]]")]);
+    assert_eq!(show(ret), "error: Entry must be a [key, value] array");
+  }
+  #[test]
+  fn assign_and_delete_modify_the_target() {
+    let handler = handler();
+    let target = var("{\"a\":1}");
+    let ret = call(
+      &handler,
+      "Object.assign",
+      vec![
+        target.clone(),
+        var("{\"b\":2}"),
+        var("null"),
+        var("{\"a\":3}"),
+      ],
+    );
+    assert_eq!(ret.to_string(), "{\"a\":3,\"b\":2}");
+    assert_eq!(target.to_string(), "{\"a\":3,\"b\":2}");
+    let run = |key: &str| call(&handler, "Object.delete", vec![target.clone(), var(key)]);
+    assert_eq!(run("\"a\"").to_string(), "true");
+    assert_eq!(run("\"a\"").to_string(), "false");
+    assert_eq!(target.to_string(), "{\"b\":2}");
+  }
+  #[test]
+  fn frozen_and_sealed_objects_reject_changes() {
+    let handler = handler();
+    let run = |name: &str, args: Vec<Var>| show(handler.call_builtin(name, None, args));
+    let sealed = var("{\"a\":1}");
+    call(&handler, "Object.seal", vec![sealed.clone()]);
+    assert_eq!(run("Object.isSealed", vec![sealed.clone()]), "true");
+    assert_eq!(run("Object.isFrozen", vec![sealed.clone()]), "false");
+    assert_eq!(
+      run("Object.assign", vec![sealed.clone(), var("{\"a\":2}")]),
+      "{\"a\":2}"
+    );
+    assert_eq!(
+      run("Object.assign", vec![sealed.clone(), var("{\"b\":2}")]),
+      "error: Cannot add property 'b', object is not extensible"
+    );
+    assert_eq!(
+      run("Object.delete", vec![sealed.clone(), var("\"a\"")]),
+      "error: Cannot delete property 'a'"
+    );
+    let frozen = var("{\"a\":1}");
+    call(&handler, "Object.freeze", vec![frozen.clone()]);
+    assert_eq!(run("Object.isFrozen", vec![frozen.clone()]), "true");
+    assert_eq!(run("Object.isSealed", vec![frozen.clone()]), "true");
+    assert_eq!(
+      run("Object.assign", vec![frozen.clone(), var("{\"a\":2}")]),
+      "error: Cannot assign to read only property 'a'"
+    );
+    assert_eq!(frozen.to_string(), "{\"a\":1}");
+    call(&handler, "Object.seal", vec![frozen.clone()]);
+    assert_eq!(run("Object.isFrozen", vec![frozen]), "true");
+    assert_eq!(run("Object.isFrozen", vec![var("{\"a\":1}")]), "false");
+  }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use utf8_slice;
 #[derive(PartialEq)]
 pub enum QuoteStatus {
@@ -10,6 +11,11 @@ pub struct LppStatus {
   pub splash: bool,
   pub brace: usize,
 }
+impl Default for LppStatus {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl LppStatus {
   pub fn new() -> Self {
     LppStatus {
@@ -83,6 +89,11 @@ pub struct Lpp {
   name: String,
   args: String,
 }
+impl Default for Lpp {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl Lpp {
   pub fn name(&self) -> &String {
     &self.name
@@ -105,12 +116,13 @@ impl From<(String, String)> for Lpp {
     }
   }
 }
-impl ToString for Lpp {
-  fn to_string(&self) -> String {
-    format!(
+impl fmt::Display for Lpp {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(
+      f,
       "{}{}{}",
       self.name,
-      if (utf8_slice::len(self.args.as_str()) == 0 && self.name != "")
+      if (utf8_slice::len(self.args.as_str()) == 0 && !self.name.is_empty())
         || (utf8_slice::len(self.args.as_str()) != 0 && self.args.starts_with('('))
         || utf8_slice::len(self.name.as_str()) == 0
       {
@@ -147,13 +159,15 @@ impl Lpp {
       if item == '\n' || item == '\t' {
         continue;
       }
-      if (item == '{' || item == '(') && status.quote == QuoteStatus::None && status.brace == 0 {
-        if item != '{' || lastchar != ')' {
-          return Lpp::from((
-            utf8_slice::slice(str, 0, utf8_slice::len(str) - i - 1).to_string(),
-            utf8_slice::slice(str, utf8_slice::len(str) - i - 1, utf8_slice::len(str)).to_string(),
-          ));
-        }
+      if (item == '{' || item == '(')
+        && status.quote == QuoteStatus::None
+        && status.brace == 0
+        && (item != '{' || lastchar != ')')
+      {
+        return Lpp::from((
+          utf8_slice::slice(str, 0, utf8_slice::len(str) - i - 1).to_string(),
+          utf8_slice::slice(str, utf8_slice::len(str) - i - 1, utf8_slice::len(str)).to_string(),
+        ));
       }
       lastchar = item;
     }
@@ -0,0 +1,437 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::symbol::Symbol;
+use super::util::{array, cell, get_string, receiver, type_error};
+use super::var::{ValueType, Var};
+use indexmap::IndexMap;
+use regex::{Captures, Regex, RegexBuilder};
+use std::cell::RefCell;
+use std::rc::Rc;
+/// 编译缓存最多保存的正则表达式数量。
+const CACHE_SIZE: usize = 256;
+thread_local! {
+  /// 以 `(source, flags)` 为键，按最近使用的顺序排列，超出容量时淘汰最久未使用的。
+  static CACHE: RefCell<IndexMap<(String, String), Rc<Regex>>> = RefCell::new(IndexMap::new());
+}
+/// 正则表达式。
+/// 字面量写作 `r"\d+"`，标志紧跟在引号之后，如 `r"ab+c"gi`：
+/// - `g`: 全局匹配，影响 `match` 与 `replace`；
+/// - `i`: 忽略大小写；
+/// - `m`: `^` 与 `$` 匹配每一行的开头与结尾；
+/// - `s`: `.` 匹配换行符；
+/// - `x`: 忽略模式中的空白与 `#` 注释。
+#[derive(Clone)]
+pub struct RegexValue {
+  pub source: String,
+  pub flags: String,
+  pub regex: Rc<Regex>,
+}
+impl PartialEq for RegexValue {
+  fn eq(&self, other: &Self) -> bool {
+    self.source == other.source && self.flags == other.flags
+  }
+}
+impl RegexValue {
+  pub fn global(&self) -> bool {
+    self.flags.contains('g')
+  }
+}
+/// 编译正则表达式。相同的 `source` 与 `flags` 只会编译一次，循环中重复构造不会重新编译。
+/// ```
+/// let re = pattern::compile("(\\w+)@(\\w+)", "i").unwrap();
+/// assert!(re.regex.is_match("Me@Example"));
+/// ```
+pub fn compile(source: &str, flags: &str) -> Result<RegexValue, Error> {
+  for (index, item) in flags.char_indices() {
+    if !"gimsx".contains(item) || flags[..index].contains(item) {
+      return Err(Error::from(
+        format!("Invalid regular expression flags '{}'", flags).as_str(),
+      ));
+    }
+  }
+  let key = (source.to_string(), flags.replace('g', ""));
+  let cached = CACHE.with(|cache| {
+    let mut cache = cache.borrow_mut();
+    let index = cache.get_index_of(&key)?;
+    let last = cache.len() - 1;
+    cache.move_index(index, last);
+    cache.get_index(last).map(|(_, val)| val.clone())
+  });
+  let regex = match cached {
+    Some(val) => val,
+    None => {
+      let val = RegexBuilder::new(source)
+        .case_insensitive(key.1.contains('i'))
+        .multi_line(key.1.contains('m'))
+        .dot_matches_new_line(key.1.contains('s'))
+        .ignore_whitespace(key.1.contains('x'))
+        .build()
+        .map_err(|err| Error::new(format!("Invalid regular expression: {}", err)))?;
+      let val = Rc::new(val);
+      CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.len() >= CACHE_SIZE {
+          cache.shift_remove_index(0);
+        }
+        cache.insert(key, val.clone());
+      });
+      val
+    }
+  };
+  Ok(RegexValue {
+    source: source.to_string(),
+    flags: flags.to_string(),
+    regex,
+  })
+}
+/// 清空编译缓存。
+pub fn clear_cache() {
+  CACHE.with(|cache| cache.borrow_mut().clear());
+}
+/// 字节偏移换算为字符下标。
+fn char_index(str: &str, offset: usize) -> i64 {
+  str[..offset].chars().count() as i64
+}
+/// 全部捕获组，未参与匹配的组为 `null`。第一个元素是整个匹配。
+fn groups(caps: &Captures) -> Vec<Var> {
+  caps
+    .iter()
+    .map(|item| match item {
+      Some(item) => Var::String(item.as_str().to_string()),
+      None => Var::Null(()),
+    })
+    .collect()
+}
+/// 匹配的详细信息：`{match, index, captures, groups}`。
+/// `captures` 为编号的捕获组，`groups` 为命名的捕获组。
+fn detail(re: &RegexValue, caps: &Captures, str: &str) -> Var {
+  let all = groups(caps);
+  let mut named: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  for (index, name) in re.regex.capture_names().enumerate() {
+    if let Some(name) = name {
+      named.insert(Symbol::from(name), cell(all[index].clone()));
+    }
+  }
+  let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  ret.insert(Symbol::from("match"), cell(all[0].clone()));
+  ret.insert(
+    Symbol::from("index"),
+    cell(Var::Integer(char_index(
+      str,
+      caps.get(0).map_or(0, |item| item.start()),
+    ))),
+  );
+  ret.insert(Symbol::from("captures"), cell(array(all[1..].to_vec())));
+  ret.insert(Symbol::from("groups"), cell(Var::from(named)));
+  Var::from(ret)
+}
+/// 按替换模板展开一次匹配：
+/// `$$` 为 `$`，`$&` 为整个匹配，`$1`～`$99` 为编号的捕获组，`$<name>` 为命名的捕获组。
+/// 无法识别的 `$` 原样保留。
+fn expand(caps: &Captures, template: &str, out: &mut String) {
+  let mut rest = template;
+  while let Some(index) = rest.find('$') {
+    *out += &rest[..index];
+    rest = &rest[index + 1..];
+    if let Some(tail) = rest.strip_prefix('$') {
+      out.push('$');
+      rest = tail;
+    } else if let Some(tail) = rest.strip_prefix('&') {
+      *out += caps.get(0).map_or("", |item| item.as_str());
+      rest = tail;
+    } else if let Some((name, tail)) = rest.strip_prefix('<').and_then(|tail| tail.split_once('>'))
+    {
+      *out += caps.name(name).map_or("", |item| item.as_str());
+      rest = tail;
+    } else {
+      let digits = rest
+        .chars()
+        .take(2)
+        .take_while(|item| item.is_ascii_digit())
+        .count();
+      // 两位数的组不存在时，退回一位数的组
+      let group = (1..=digits)
+        .rev()
+        .map(|len| (len, rest[..len].parse::<usize>().unwrap_or_default()))
+        .find(|(_, index)| *index > 0 && *index < caps.len());
+      match group {
+        Some((len, index)) => {
+          *out += caps.get(index).map_or("", |item| item.as_str());
+          rest = &rest[len..];
+        }
+        None => out.push('$'),
+      }
+    }
+  }
+  *out += rest;
+}
+/// 替换 `str` 中的匹配。`global` 为 `false` 时只替换第一个。
+/// `replacement` 为字符串时按 `$1`、`$<name>` 等模板展开；
+/// 为函数时以 `(match, ...captures, index, str)` 调用，返回值转换为字符串后作为替换内容。
+pub fn replace<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  str: &str,
+  re: &RegexValue,
+  replacement: Option<&Var>,
+  global: bool,
+) -> Result<String, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let mut ret = String::new();
+  let mut last = 0;
+  for caps in re.regex.captures_iter(str) {
+    let whole = match caps.get(0) {
+      Some(whole) => whole,
+      None => continue,
+    };
+    ret += &str[last..whole.start()];
+    match replacement {
+      Some(Var::String(template)) => expand(&caps, template, &mut ret),
+      Some(Var::Function(func)) => {
+        let mut args = groups(&caps);
+        args.push(Var::Integer(char_index(str, whole.start())));
+        args.push(Var::String(str.to_string()));
+        match handler.runfunc(func, args)? {
+          Var::String(val) => ret += &val,
+          val => ret += &val.to_string(),
+        }
+      }
+      Some(_) => return Err(LppError::Error(Error::from("String or Function expected"))),
+      None => return Err(LppError::Error(Error::from("Too few arguments given"))),
+    }
+    last = whole.end();
+    if !global {
+      break;
+    }
+  }
+  ret += &str[last..];
+  Ok(ret)
+}
+/// 按正则表达式分割 `str`，最多返回 `limit` 段。
+pub fn split(str: &str, re: &RegexValue, limit: usize) -> Var {
+  array(
+    re.regex
+      .split(str)
+      .take(limit)
+      .map(|item| Var::String(item.to_string()))
+      .collect(),
+  )
+}
+/// 取正则表达式参数。字符串将作为不带标志的模式编译。
+fn get_regex(args: &[Var], index: usize) -> Result<RegexValue, LppError> {
+  match args.get(index) {
+    Some(Var::Regex(val)) => Ok(val.clone()),
+    Some(Var::String(val)) => compile(val, "").map_err(LppError::Error),
+    Some(_) => Err(LppError::Error(Error::from("RegExp expected"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 取接收者中的字符串与参数中的正则表达式。
+fn operands(
+  this: Option<Rc<RefCell<Var>>>,
+  args: &[Var],
+) -> Result<(String, RegexValue), LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::String(str) => Ok((str.clone(), get_regex(args, 0)?)),
+    _ => Err(type_error()),
+  }
+}
+fn regexp<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let flags = match args.get(1) {
+    None | Some(Var::Null(_)) => String::new(),
+    _ => get_string(&args, 1)?,
+  };
+  let source = match args.first() {
+    Some(Var::Regex(val)) => val.source.clone(),
+    _ => get_string(&args, 0)?,
+  };
+  Ok(Var::Regex(
+    compile(&source, &flags).map_err(LppError::Error)?,
+  ))
+}
+fn regexp_escape<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(regex::escape(&get_string(&args, 0)?)))
+}
+fn source<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Regex(re) => Ok(Var::String(re.source.clone())),
+    _ => Err(type_error()),
+  }
+}
+fn flags<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::Regex(re) => Ok(Var::String(re.flags.clone())),
+    _ => Err(type_error()),
+  }
+}
+/// `re.test(str)` 与 `str.test(re)`。
+fn test<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (str, re) = match &*receiver(this)?.borrow() {
+    Var::Regex(re) => (get_string(&args, 0)?, re.clone()),
+    Var::String(str) => (str.clone(), get_regex(&args, 0)?),
+    _ => return Err(type_error()),
+  };
+  Ok(Var::Boolean(re.regex.is_match(&str)))
+}
+/// 没有全局标志时返回第一个匹配及其捕获组，有全局标志时返回所有匹配。没有匹配时返回 `null`。
+fn match_<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (str, re) = operands(this, &args)?;
+  if re.global() {
+    let items: Vec<Var> = re
+      .regex
+      .find_iter(&str)
+      .map(|item| Var::String(item.as_str().to_string()))
+      .collect();
+    return Ok(if items.is_empty() {
+      Var::Null(())
+    } else {
+      array(items)
+    });
+  }
+  Ok(match re.regex.captures(&str) {
+    Some(caps) => array(groups(&caps)),
+    None => Var::Null(()),
+  })
+}
+/// 返回所有匹配的详细信息，见 `detail`。
+fn match_all<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (str, re) = operands(this, &args)?;
+  let items: Vec<Var> = re
+    .regex
+    .captures_iter(&str)
+    .map(|caps| detail(&re, &caps, &str))
+    .collect();
+  Ok(array(items))
+}
+/// 在 `Handler` 中注册 `RegExp`、`RegExp.escape` 以及正则表达式相关的方法。
+/// 字符串的 `replace`、`replaceAll` 与 `split` 也接受正则表达式，见 `string` 模块。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let string: &[ValueType] = &[ValueType::String];
+  let regex: &[ValueType] = &[ValueType::Regex];
+  handler.add_builtin("RegExp", regexp::<Parser>);
+  handler.add_builtin("RegExp.escape", regexp_escape::<Parser>);
+  handler.add_native(
+    "source",
+    NativeFunc::from((regex, source::<Parser> as _, true)),
+  );
+  handler.add_native(
+    "flags",
+    NativeFunc::from((regex, flags::<Parser> as _, true)),
+  );
+  handler.add_native(
+    "test",
+    NativeFunc::from((
+      &[ValueType::String, ValueType::Regex] as &[ValueType],
+      test::<Parser> as _,
+      false,
+    )),
+  );
+  let methods: [(&str, BuiltinFunc<Parser>); 2] = [
+    ("match", match_::<Parser>),
+    ("matchAll", match_all::<Parser>),
+  ];
+  for (name, func) in methods {
+    handler.add_native(name, NativeFunc::from((string, func, false)));
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, method, show, var};
+  use super::*;
+  #[test]
+  fn literals_compile_once() {
+    let re = var(r#"r"a\"b\d"gi"#);
+    assert_eq!(re.to_string(), r#"r"a\"b\d"gi"#);
+    let handler = handler();
+    assert_eq!(
+      method(&handler, re.clone(), "source", vec![]).to_string(),
+      r#""a\"b\\d""#
+    );
+    assert_eq!(method(&handler, re, "flags", vec![]).to_string(), "\"gi\"");
+    let first = compile("x+", "g").ok().unwrap();
+    let second = compile("x+", "").ok().unwrap();
+    assert!(Rc::ptr_eq(&first.regex, &second.regex));
+    assert!(compile("x+", "gg").is_err());
+    assert!(compile("x+", "q").is_err());
+    let err = compile("(", "").err().unwrap();
+    assert!(err.err.starts_with("Invalid regular expression: "));
+  }
+  #[test]
+  fn string_methods_accept_patterns() {
+    let handler = handler();
+    let str = var("\"a1-b22-c333\"");
+    let run = |name: &str, args: Vec<Var>| method(&handler, str.clone(), name, args).to_string();
+    assert_eq!(run("test", vec![var(r#"r"\d{3}""#)]), "true");
+    assert_eq!(
+      run("match", vec![var(r#"r"([a-z])(\d+)""#)]),
+      "[\"a1\",\"a\",\"1\"]"
+    );
+    assert_eq!(
+      run("match", vec![var(r#"r"\d+"g"#)]),
+      "[\"1\",\"22\",\"333\"]"
+    );
+    assert_eq!(run("match", vec![var(r#"r"z""#)]), "null");
+    assert_eq!(
+      run("matchAll", vec![var(r#"r"(?P<d>\d)\b""#)]),
+      "[{\"match\":\"1\",\"index\":1,\"captures\":[\"1\"],\"groups\":{\"d\":\"1\"}},\
+       {\"match\":\"2\",\"index\":5,\"captures\":[\"2\"],\"groups\":{\"d\":\"2\"}},\
+       {\"match\":\"3\",\"index\":10,\"captures\":[\"3\"],\"groups\":{\"d\":\"3\"}}]"
+    );
+    assert_eq!(
+      run("replace", vec![var(r#"r"([a-z])(\d+)""#), var("\"$2$1\"")]),
+      "\"1a-b22-c333\""
+    );
+    assert_eq!(
+      run("replace", vec![var(r#"r"([a-z])(\d+)"g"#), var("\"$2$1\"")]),
+      "\"1a-22b-333c\""
+    );
+    assert_eq!(
+      run("replaceAll", vec![var(r#"r"\d""#), var("\"#\"")]),
+      "\"a#-b##-c###\""
+    );
+    assert_eq!(
+      run("split", vec![var(r#"r"-?\d+-?""#)]),
+      "[\"a\",\"b\",\"c\",\"\"]"
+    );
+    assert_eq!(
+      show(handler.call_native("match", Rc::new(RefCell::new(str.clone())), vec![var("1")])),
+      "error: RegExp expected"
+    );
+    assert_eq!(
+      call(&handler, "RegExp.escape", vec![var("\"a.b\"")]).to_string(),
+      r#""a\\.b""#
+    );
+  }
+}
@@ -0,0 +1,217 @@
+use super::lpp::{LppError, RetVal};
+use super::var::Var;
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::{Component, Path, PathBuf};
+/// 对宿主产生影响或依赖宿主状态的能力。
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Capability {
+  /// 读取该路径及其中的所有文件。
+  Read(PathBuf),
+  /// 读写该路径及其中的所有文件。
+  Write(PathBuf),
+  /// 读取与修改环境变量。
+  Env,
+  /// 启动子进程。
+  Process,
+  /// 读取当前时间。
+  Clock,
+  /// 宿主自定义的能力，以名称区分，用于宿主注册的 `NativeFunc`。
+  Host(String),
+}
+impl fmt::Display for Capability {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Capability::Read(path) => write!(f, "read access to '{}'", path.display()),
+      Capability::Write(path) => write!(f, "write access to '{}'", path.display()),
+      Capability::Env => write!(f, "environment access"),
+      Capability::Process => write!(f, "subprocess execution"),
+      Capability::Clock => write!(f, "clock access"),
+      Capability::Host(name) => write!(f, "capability '{}'", name),
+    }
+  }
+}
+impl Capability {
+  /// 路径按 `resolve` 解析，其他能力不变。
+  fn normalize(self) -> Capability {
+    match self {
+      Capability::Read(path) => Capability::Read(resolve(&path)),
+      Capability::Write(path) => Capability::Write(resolve(&path)),
+      val => val,
+    }
+  }
+  /// 已授予的能力 `self` 是否包含 `other`。读写权限同时包含读权限。
+  fn covers(&self, other: &Capability) -> bool {
+    match (self, other) {
+      (Capability::Read(dir), Capability::Read(path))
+      | (Capability::Write(dir), Capability::Read(path))
+      | (Capability::Write(dir), Capability::Write(path)) => path.starts_with(dir),
+      (left, right) => left == right,
+    }
+  }
+}
+/// 把路径解析为不含 `.`、`..` 与符号链接的绝对路径，以免借助它们逃出允许的目录。
+/// 路径中尚不存在的部分按字面处理。
+pub fn resolve(path: &Path) -> PathBuf {
+  let path = if path.is_absolute() {
+    path.to_path_buf()
+  } else {
+    env::current_dir().unwrap_or_default().join(path)
+  };
+  let items: Vec<Component> = path.components().collect();
+  for split in (1..=items.len()).rev() {
+    if let Ok(mut ret) = items[..split].iter().collect::<PathBuf>().canonicalize() {
+      for item in &items[split..] {
+        match item {
+          Component::ParentDir => {
+            ret.pop();
+          }
+          Component::CurDir => {}
+          item => ret.push(item),
+        }
+      }
+      return ret;
+    }
+  }
+  path
+}
+type Prompt = Box<dyn Fn(&Capability) -> bool>;
+/// 权限。所有影响宿主的内置函数都要先经过它的检查，默认不授予任何能力。
+/// 未授予的能力可以交给 `prompt` 询问，同意后视为已授予。
+/// 没有设置 `prompt` 时，开启 `interactive` 后在终端中用 `ask` 询问，供 REPL 使用。
+/// 纯模式下任何能力都不可用，脚本不会产生任何效果。
+/// ```
+/// handler.permissions.grant(Capability::Read(PathBuf::from("/usr/share/dict")));
+/// handler.permissions.grant(Capability::Clock);
+/// handler.permissions.set_interactive(true);
+/// ```
+pub struct Permissions {
+  granted: RefCell<Vec<Capability>>,
+  prompt: RefCell<Option<Prompt>>,
+  interactive: Cell<bool>,
+  pure: bool,
+}
+impl Default for Permissions {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl Permissions {
+  pub fn new() -> Self {
+    Permissions {
+      granted: RefCell::new(vec![]),
+      prompt: RefCell::new(None),
+      interactive: Cell::new(false),
+      pure: false,
+    }
+  }
+  /// 纯模式。`grant` 与 `prompt` 都不起作用。
+  pub fn pure() -> Self {
+    Permissions {
+      pure: true,
+      ..Permissions::new()
+    }
+  }
+  pub fn is_pure(&self) -> bool {
+    self.pure
+  }
+  pub fn grant(&self, cap: Capability) {
+    let cap = cap.normalize();
+    let mut granted = self.granted.borrow_mut();
+    if !granted.contains(&cap) {
+      granted.push(cap);
+    }
+  }
+  /// 撤销之前授予的能力。只撤销完全相同的一项，不影响范围更大的授权。
+  pub fn revoke(&self, cap: Capability) {
+    let cap = cap.normalize();
+    self.granted.borrow_mut().retain(|item| *item != cap);
+  }
+  /// 设置询问函数，返回 `true` 表示同意。
+  pub fn set_prompt<F: Fn(&Capability) -> bool + 'static>(&self, prompt: F) {
+    *self.prompt.borrow_mut() = Some(Box::new(prompt));
+  }
+  /// 开启后，未授予的能力在没有 `prompt` 时通过 `ask` 在终端中询问。默认关闭。
+  pub fn set_interactive(&self, val: bool) {
+    self.interactive.set(val);
+  }
+  /// 是否已经授予 `cap`，不会询问。
+  pub fn allows(&self, cap: &Capability) -> bool {
+    let cap = cap.clone().normalize();
+    !self.pure && self.granted.borrow().iter().any(|item| item.covers(&cap))
+  }
+  /// 检查 `cap`，未授予时询问。不允许时以 `ThrowValue` 抛出原因，脚本可以捕获。
+  pub fn check(&self, cap: Capability) -> Result<(), LppError> {
+    let cap = cap.normalize();
+    if self.pure {
+      return Err(LppError::UnexpectedReturn(RetVal::ThrowValue(Var::String(
+        format!("Permission denied: {} is not available in pure mode", cap),
+      ))));
+    }
+    if self.allows(&cap) {
+      return Ok(());
+    }
+    let allowed = match &*self.prompt.borrow() {
+      Some(prompt) => prompt(&cap),
+      None => self.interactive.get() && ask(&cap),
+    };
+    if allowed {
+      self.grant(cap);
+      return Ok(());
+    }
+    Err(LppError::UnexpectedReturn(RetVal::ThrowValue(Var::String(
+      format!("Permission denied: {} is not allowed", cap),
+    ))))
+  }
+}
+/// 在终端中询问，`interactive` 开启时使用。输入 `y` 表示同意，其余输入与读取失败都视为拒绝。
+pub fn ask(cap: &Capability) -> bool {
+  eprint!("Allow {}? [y/N] ", cap);
+  let _ = io::stderr().flush();
+  let mut line = String::new();
+  match io::stdin().lock().read_line(&mut line) {
+    Ok(_) => line.trim().eq_ignore_ascii_case("y"),
+    Err(_) => false,
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::rc::Rc;
+  #[test]
+  fn prompt_grants_and_remembers() {
+    let permissions = Permissions::new();
+    assert!(permissions.check(Capability::Env).is_err());
+    let asked = Rc::new(Cell::new(0));
+    let count = asked.clone();
+    permissions.set_prompt(move |cap| {
+      count.set(count.get() + 1);
+      *cap == Capability::Env
+    });
+    assert!(permissions.check(Capability::Env).is_ok());
+    assert!(permissions.check(Capability::Env).is_ok());
+    assert!(permissions.check(Capability::Process).is_err());
+    assert_eq!(asked.get(), 2);
+  }
+  #[test]
+  fn pure_mode_ignores_grants_and_prompt() {
+    let permissions = Permissions::pure();
+    permissions.grant(Capability::Clock);
+    permissions.set_prompt(|_| true);
+    permissions.set_interactive(true);
+    assert!(!permissions.allows(&Capability::Clock));
+    assert!(permissions.check(Capability::Clock).is_err());
+  }
+  #[test]
+  fn write_grant_covers_reads_below_it() {
+    let permissions = Permissions::new();
+    let dir = env::temp_dir();
+    permissions.grant(Capability::Write(dir.clone()));
+    assert!(permissions.allows(&Capability::Read(dir.join("a/b"))));
+    assert!(!permissions.allows(&Capability::Read(dir.join("../outside"))));
+    permissions.revoke(Capability::Write(dir.clone()));
+    assert!(!permissions.allows(&Capability::Read(dir)));
+  }
+}
@@ -0,0 +1,353 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::permission::Capability;
+use super::symbol::Symbol;
+use super::util::{cell, get_callback, get_string, throw};
+use super::var::Var;
+use indexmap::IndexMap;
+use std::cell::RefCell;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::rc::Rc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+/// 参数列表。省略或为 `null` 时没有参数。
+fn get_args(args: &[Var], index: usize) -> Result<Vec<String>, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(vec![]),
+    Some(Var::Array(val)) => {
+      let mut ret = vec![];
+      for item in val.borrow().iter() {
+        match &*item.borrow() {
+          Var::String(item) => ret.push(item.clone()),
+          _ => return Err(LppError::Error(Error::from("Argument must be a string"))),
+        }
+      }
+      Ok(ret)
+    }
+    Some(_) => Err(LppError::Error(Error::from("Array expected"))),
+  }
+}
+/// 不由 `Command` 处理的选项。
+#[derive(Default)]
+struct Options {
+  /// 要写入标准输入的内容。
+  input: Option<Vec<u8>>,
+  /// 超过此时间仍未结束的子进程将被终止。
+  timeout: Option<Duration>,
+}
+/// 按选项 `{cwd, env, stdin, timeout}` 构造命令。
+/// `env` 中的变量在继承的环境变量之上设置，值为 `null` 的变量被删除。
+/// `timeout` 为毫秒数或 `Duration`。
+fn build(args: &[Var]) -> Result<(Command, Options), LppError> {
+  let mut cmd = Command::new(get_string(args, 0)?);
+  cmd.args(get_args(args, 1)?);
+  let mut ret = Options::default();
+  let options = match args.get(2) {
+    None | Some(Var::Null(_)) => return Ok((cmd, ret)),
+    Some(Var::Object(val)) => val.clone(),
+    Some(_) => return Err(LppError::Error(Error::from("Object expected"))),
+  };
+  for (key, value) in options.borrow().iter() {
+    match (&*key.as_str(), &*value.borrow()) {
+      (_, Var::Null(_)) => {}
+      ("cwd", Var::String(val)) => {
+        cmd.current_dir(val);
+      }
+      ("env", Var::Object(val)) => {
+        for (key, value) in val.borrow().iter() {
+          match &*value.borrow() {
+            Var::String(value) => {
+              cmd.env(&*key.as_str(), value);
+            }
+            Var::Null(_) => {
+              cmd.env_remove(&*key.as_str());
+            }
+            _ => {
+              return Err(LppError::Error(Error::from(
+                "Environment variable must be a string",
+              )))
+            }
+          }
+        }
+      }
+      ("stdin", Var::String(val)) => ret.input = Some(val.clone().into_bytes()),
+      ("stdin", Var::Bytes(val)) => ret.input = Some(val.clone()),
+      ("timeout", Var::Integer(val)) if *val >= 0 => {
+        ret.timeout = Some(Duration::from_millis(*val as u64))
+      }
+      ("timeout", Var::Duration(val)) => match val.to_std() {
+        Ok(val) => ret.timeout = Some(val),
+        Err(_) => return Err(LppError::Error(Error::from("Timeout must not be negative"))),
+      },
+      (key, _) => {
+        return Err(LppError::Error(Error::new(format!(
+          "Invalid option '{}'",
+          key
+        ))))
+      }
+    }
+  }
+  Ok((cmd, ret))
+}
+/// 启动子进程。标准输入、输出与错误都通过管道连接，没有输入时立即关闭标准输入。
+fn spawn(mut cmd: Command, name: &str) -> Result<Child, LppError> {
+  cmd
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|err| throw(format!("Failed to run '{}': {}", name, err)))
+}
+/// 在另一个线程中写入标准输入，以免子进程的输出填满管道时互相等待。
+fn feed(stdin: Option<ChildStdin>, input: Option<Vec<u8>>) -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    if let (Some(mut stdin), Some(input)) = (stdin, input) {
+      // 子进程可能不读取输入就退出，此时写入失败是正常的
+      let _ = stdin.write_all(&input);
+    }
+  })
+}
+fn timed_out(name: &str) -> LppError {
+  throw(format!("Process '{}' timed out", name))
+}
+/// 等待子进程结束。超过 `deadline` 时终止子进程并抛出异常。
+fn wait(child: &mut Child, deadline: Option<Instant>, name: &str) -> Result<ExitStatus, LppError> {
+  let failed = |err: io::Error| throw(format!("Failed to run '{}': {}", name, err));
+  let deadline = match deadline {
+    Some(deadline) => deadline,
+    None => return child.wait().map_err(failed),
+  };
+  loop {
+    match child.try_wait().map_err(failed)? {
+      Some(status) => return Ok(status),
+      None if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+      None => {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(timed_out(name));
+      }
+    }
+  }
+}
+/// 在另一个线程中读取全部输出。
+fn read_all<R: Read + Send + 'static>(source: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+  thread::spawn(move || {
+    let mut ret = vec![];
+    if let Some(mut source) = source {
+      let _ = source.read_to_end(&mut ret);
+    }
+    ret
+  })
+}
+fn exit_code(status: ExitStatus) -> Var {
+  match status.code() {
+    Some(code) => Var::Integer(code as i64),
+    None => Var::Null(()),
+  }
+}
+/// `process.run(cmd, args, {cwd, env, stdin, timeout})`。
+/// 等待子进程结束，返回 `{code, stdout, stderr}`。被信号终止时 `code` 为 `null`。
+/// 超时时终止子进程并抛出异常。
+fn run<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let (cmd, options) = build(&args)?;
+  let name = get_string(&args, 0)?;
+  let mut child = spawn(cmd, &name)?;
+  let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+  let writer = feed(child.stdin.take(), options.input);
+  let stdout = read_all(child.stdout.take());
+  let stderr = read_all(child.stderr.take());
+  // 超时时不等待读取线程，理由同 `stream`
+  let status = wait(&mut child, deadline, &name)?;
+  let _ = writer.join();
+  let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+  ret.insert(Symbol::from("code"), cell(exit_code(status)));
+  for (key, output) in [("stdout", stdout), ("stderr", stderr)] {
+    let output = output.join().unwrap_or_default();
+    ret.insert(
+      Symbol::from(key),
+      cell(Var::String(String::from_utf8_lossy(&output).into_owned())),
+    );
+  }
+  Ok(Var::from(ret))
+}
+/// 逐行读取 `source`，连同来源的名称发送到 `sender`。
+fn forward<R: Read + Send + 'static>(
+  source: Option<R>,
+  name: &'static str,
+  sender: mpsc::Sender<(&'static str, io::Result<Vec<u8>>)>,
+) -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    let mut source = match source {
+      Some(source) => BufReader::new(source),
+      None => return,
+    };
+    loop {
+      let mut line = vec![];
+      match source.read_until(b'\n', &mut line) {
+        Ok(0) => break,
+        Ok(_) => {
+          if sender.send((name, Ok(line))).is_err() {
+            break;
+          }
+        }
+        Err(err) => {
+          let _ = sender.send((name, Err(err)));
+          break;
+        }
+      }
+    }
+  })
+}
+/// `process.stream(cmd, args, {cwd, env, stdin, timeout}, callback)`。
+/// 子进程每输出一行就以 `(line, stream)` 调用 `callback`，`line` 含有行尾的换行符，
+/// `stream` 为 `"stdout"` 或 `"stderr"`。返回退出码，被信号终止时为 `null`。
+/// 回调抛出异常或超时时终止子进程。
+fn stream<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let (cmd, options) = build(&args)?;
+  let func = get_callback(&args, 3)?;
+  let name = get_string(&args, 0)?;
+  let mut child = spawn(cmd, &name)?;
+  let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+  // 不等待这些线程：正常结束时它们已经退出；提前结束时，
+  // 子进程启动的其他进程可能仍然持有管道，等待它们会一直阻塞
+  feed(child.stdin.take(), options.input);
+  let (sender, receiver) = mpsc::channel();
+  forward(child.stdout.take(), "stdout", sender.clone());
+  forward(child.stderr.take(), "stderr", sender);
+  let mut ret = Ok(());
+  loop {
+    let next = match deadline {
+      Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+      None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+    };
+    let (source, line) = match next {
+      Ok(next) => next,
+      Err(RecvTimeoutError::Timeout) => {
+        ret = Err(timed_out(&name));
+        let _ = child.kill();
+        break;
+      }
+      Err(RecvTimeoutError::Disconnected) => break,
+    };
+    ret = match line {
+      Ok(line) => handler
+        .runfunc(
+          &func,
+          vec![
+            Var::String(String::from_utf8_lossy(&line).into_owned()),
+            Var::String(source.to_string()),
+          ],
+        )
+        .map(|_| ()),
+      Err(err) => Err(throw(format!("Failed to read {}: {}", source, err))),
+    };
+    if ret.is_err() {
+      let _ = child.kill();
+      break;
+    }
+  }
+  // 提前结束时丢弃接收端，读取线程在发送失败后退出
+  drop(receiver);
+  let status = wait(&mut child, deadline, &name);
+  ret?;
+  Ok(exit_code(status?))
+}
+/// 在 `Handler` 中注册 `process.run` 与 `process.stream`，都需要 `Capability::Process`。
+/// 启动失败与读取错误都以 `ThrowValue` 抛出原因。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let funcs: [(&str, BuiltinFunc<Parser>); 2] = [
+    ("process.run", run::<Parser>),
+    ("process.stream", stream::<Parser>),
+  ];
+  for (name, func) in funcs {
+    handler.builtin.insert(
+      String::from(name),
+      NativeFunc::from(func).requires(Capability::Process),
+    );
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, show, var, Lpp};
+  use super::*;
+  use std::time::Instant;
+  fn granted() -> Handler<Lpp> {
+    let handler = handler();
+    handler.permissions.grant(Capability::Process);
+    handler
+  }
+  fn field(val: &Var, key: &str) -> String {
+    match val {
+      Var::Object(obj) => obj.borrow()[&Symbol::from(key)].borrow().to_string(),
+      _ => panic!("Object expected"),
+    }
+  }
+  #[test]
+  fn run_captures_exit_code_and_output() {
+    let handler = granted();
+    let ret = call(
+      &handler,
+      "process.run",
+      vec![var("\"echo\""), var("[\"hi\"]")],
+    );
+    assert_eq!(field(&ret, "code"), "0");
+    assert_eq!(field(&ret, "stdout"), "\"hi\\n\"");
+    let ret = call(
+      &handler,
+      "process.run",
+      vec![var("\"sh\""), var("[\"-c\",\"printf err >&2; exit 3\"]")],
+    );
+    assert_eq!(field(&ret, "code"), "3");
+    assert_eq!(field(&ret, "stdout"), "\"\"");
+    assert_eq!(field(&ret, "stderr"), "\"err\"");
+  }
+  #[test]
+  fn run_passes_stdin_through() {
+    let handler = granted();
+    let ret = call(
+      &handler,
+      "process.run",
+      vec![var("\"cat\""), var("null"), var("{\"stdin\":\"abc\"}")],
+    );
+    assert_eq!(field(&ret, "stdout"), "\"abc\"");
+  }
+  #[test]
+  fn timeout_kills_the_child() {
+    let handler = granted();
+    let start = Instant::now();
+    let ret = handler.call_builtin(
+      "process.run",
+      None,
+      vec![var("\"sleep\""), var("[\"5\"]"), var("{\"timeout\":100}")],
+    );
+    assert_eq!(show(ret), "throw: \"Process \\'sleep\\' timed out\"");
+    let ret = handler.call_builtin(
+      "process.stream",
+      None,
+      vec![
+        var("\"sleep\""),
+        var("[\"5\"]"),
+        var("{\"timeout\":100}"),
+        var("func(line,stream){}"),
+      ],
+    );
+    assert_eq!(show(ret), "throw: \"Process \\'sleep\\' timed out\"");
+    assert!(start.elapsed() < Duration::from_secs(4));
+  }
+}
@@ -0,0 +1,447 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::pattern;
+use super::util::{get_integer, get_string, position, receiver, type_error};
+use super::var::{ValueType, Var};
+use std::cell::RefCell;
+use std::rc::Rc;
+fn this_str(this: Option<Rc<RefCell<Var>>>) -> Result<String, LppError> {
+  match &*receiver(this)?.borrow() {
+    Var::String(val) => Ok(val.clone()),
+    _ => Err(type_error()),
+  }
+}
+/// `repeat`、`padStart` 等生成的字符串的最大字节数，超过时返回错误，以免耗尽内存。
+pub const MAX_LENGTH: usize = 1 << 29;
+/// 检查结果的字节数 `size` 是否超过 `MAX_LENGTH`。
+fn check_length(size: Option<usize>) -> Result<(), LppError> {
+  match size {
+    Some(size) if size <= MAX_LENGTH => Ok(()),
+    _ => Err(LppError::Error(Error::from("Invalid string length"))),
+  }
+}
+/// 在 `str` 中从第 `from` 个字符开始查找 `pat`，返回字符下标。
+fn find(str: &str, pat: &str, from: usize) -> Option<usize> {
+  let rest = utf8_slice::slice(str, from, utf8_slice::len(str));
+  rest
+    .find(pat)
+    .map(|index| from + rest[..index].chars().count())
+}
+fn length<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(utf8_slice::len(&this_str(this)?) as i64))
+}
+fn substr<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let len = utf8_slice::len(&str);
+  let start = position(get_integer(&args, 0)?.unwrap_or(0), len);
+  let end = match get_integer(&args, 1)? {
+    Some(count) if count <= 0 => start,
+    Some(count) => start.saturating_add(count as usize).min(len),
+    None => len,
+  };
+  Ok(Var::String(utf8_slice::slice(&str, start, end).to_string()))
+}
+fn slice<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let len = utf8_slice::len(&str);
+  let start = position(get_integer(&args, 0)?.unwrap_or(0), len);
+  let end = position(get_integer(&args, 1)?.unwrap_or(len as i64), len);
+  Ok(Var::String(if start < end {
+    utf8_slice::slice(&str, start, end).to_string()
+  } else {
+    String::new()
+  }))
+}
+fn index_of<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let pat = get_string(&args, 0)?;
+  let from = position(get_integer(&args, 1)?.unwrap_or(0), utf8_slice::len(&str));
+  Ok(Var::Integer(match find(&str, &pat, from) {
+    Some(index) => index as i64,
+    None => -1,
+  }))
+}
+fn includes<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let pat = get_string(&args, 0)?;
+  let from = position(get_integer(&args, 1)?.unwrap_or(0), utf8_slice::len(&str));
+  Ok(Var::Boolean(find(&str, &pat, from).is_some()))
+}
+/// 分隔符可以是字符串或正则表达式。
+fn split<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let limit = match get_integer(&args, 1)? {
+    Some(limit) if limit >= 0 => limit as usize,
+    _ => usize::MAX,
+  };
+  if let Some(Var::Regex(re)) = args.first() {
+    return Ok(pattern::split(&str, re, limit));
+  }
+  let sep = get_string(&args, 0)?;
+  let items: Vec<String> = if sep.is_empty() {
+    str.chars().map(|item| item.to_string()).collect()
+  } else {
+    str
+      .split(sep.as_str())
+      .map(|item| item.to_string())
+      .collect()
+  };
+  Ok(Var::from(
+    items
+      .into_iter()
+      .take(limit)
+      .map(|item| Rc::new(RefCell::new(Var::String(item))))
+      .collect::<Vec<Rc<RefCell<Var>>>>(),
+  ))
+}
+/// 第一个参数为正则表达式时，没有全局标志只替换第一个匹配，替换内容可以引用捕获组。
+fn replace<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let str = this_str(this)?;
+  if let Some(Var::Regex(re)) = args.first() {
+    return Ok(Var::String(pattern::replace(
+      handler,
+      &str,
+      re,
+      args.get(1),
+      re.global(),
+    )?));
+  }
+  Ok(Var::String(str.replacen(
+    get_string(&args, 0)?.as_str(),
+    get_string(&args, 1)?.as_str(),
+    1,
+  )))
+}
+/// 第一个参数为正则表达式时，无论有没有全局标志都替换所有匹配。
+fn replace_all<Parser: ParserInterface>(
+  handler: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError>
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let str = this_str(this)?;
+  if let Some(Var::Regex(re)) = args.first() {
+    return Ok(Var::String(pattern::replace(
+      handler,
+      &str,
+      re,
+      args.get(1),
+      true,
+    )?));
+  }
+  let pat = get_string(&args, 0)?;
+  if pat.is_empty() {
+    return Err(LppError::Error(Error::from("Empty pattern")));
+  }
+  Ok(Var::String(
+    str.replace(pat.as_str(), get_string(&args, 1)?.as_str()),
+  ))
+}
+fn trim<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(this_str(this)?.trim().to_string()))
+}
+fn trim_start<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(this_str(this)?.trim_start().to_string()))
+}
+fn trim_end<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(this_str(this)?.trim_end().to_string()))
+}
+fn to_upper_case<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(this_str(this)?.to_uppercase()))
+}
+fn to_lower_case<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::String(this_str(this)?.to_lowercase()))
+}
+fn starts_with<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let from = position(get_integer(&args, 1)?.unwrap_or(0), utf8_slice::len(&str));
+  Ok(Var::Boolean(
+    utf8_slice::slice(&str, from, utf8_slice::len(&str))
+      .starts_with(get_string(&args, 0)?.as_str()),
+  ))
+}
+fn ends_with<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let len = utf8_slice::len(&str);
+  let end = position(get_integer(&args, 1)?.unwrap_or(len as i64), len);
+  Ok(Var::Boolean(
+    utf8_slice::slice(&str, 0, end).ends_with(get_string(&args, 0)?.as_str()),
+  ))
+}
+fn repeat<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  match get_integer(&args, 0)? {
+    Some(count) if count >= 0 => {
+      check_length(str.len().checked_mul(count as usize))?;
+      Ok(Var::String(str.repeat(count as usize)))
+    }
+    Some(_) => Err(LppError::Error(Error::from("Invalid count value"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 生成填充到 `width` 个字符所需的前缀或后缀。填充后超过 `MAX_LENGTH` 时返回错误。
+fn padding(str: &str, args: &[Var]) -> Result<String, LppError> {
+  let width = match get_integer(args, 0)? {
+    Some(width) if width > 0 => width as usize,
+    _ => 0,
+  };
+  let fill = match args.get(1) {
+    None | Some(Var::Null(_)) => String::from(" "),
+    Some(_) => get_string(args, 1)?,
+  };
+  let len = utf8_slice::len(str);
+  if width <= len || fill.is_empty() {
+    return Ok(String::new());
+  }
+  let (size, count) = (width - len, fill.chars().count());
+  let rest: usize = fill.chars().take(size % count).map(char::len_utf8).sum();
+  check_length(
+    fill
+      .len()
+      .checked_mul(size / count)
+      .and_then(|size| size.checked_add(rest + str.len())),
+  )?;
+  Ok(fill.chars().cycle().take(width - len).collect())
+}
+fn pad_start<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  Ok(Var::String(padding(&str, &args)? + str.as_str()))
+}
+fn pad_end<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  let pad = padding(&str, &args)?;
+  Ok(Var::String(str + pad.as_str()))
+}
+fn char_at<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  Ok(Var::String(match get_integer(&args, 0)?.unwrap_or(0) {
+    index if index >= 0 => str
+      .chars()
+      .nth(index as usize)
+      .map(|item| item.to_string())
+      .unwrap_or_default(),
+    _ => String::new(),
+  }))
+}
+fn code_point_at<Parser>(
+  _: &Handler<Parser>,
+  this: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = this_str(this)?;
+  Ok(match get_integer(&args, 0)?.unwrap_or(0) {
+    index if index >= 0 => match str.chars().nth(index as usize) {
+      Some(item) => Var::Integer(item as i64),
+      None => Var::Null(()),
+    },
+    _ => Var::Null(()),
+  })
+}
+fn from_code_point<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let mut ret = String::new();
+  for index in 0..args.len() {
+    match get_integer(&args, index)?
+      .and_then(|val| u32::try_from(val).ok())
+      .and_then(char::from_u32)
+    {
+      Some(item) => ret.push(item),
+      None => return Err(LppError::Error(Error::from("Invalid code point"))),
+    }
+  }
+  Ok(Var::String(ret))
+}
+/// 在 `Handler` 中注册字符串的方法与 `String.fromCodePoint`。
+/// 所有下标都以 Unicode 码点计数，负数下标从末尾开始计数。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  let string: &[ValueType] = &[ValueType::String];
+  handler.add_builtin("String.fromCodePoint", from_code_point::<Parser>);
+  handler.add_native(
+    "length",
+    NativeFunc::from((string, length::<Parser> as _, true)),
+  );
+  let methods: [(&str, BuiltinFunc<Parser>); 19] = [
+    ("substr", substr::<Parser>),
+    ("slice", slice::<Parser>),
+    ("indexOf", index_of::<Parser>),
+    ("split", split::<Parser>),
+    ("replace", replace::<Parser>),
+    ("replaceAll", replace_all::<Parser>),
+    ("trim", trim::<Parser>),
+    ("trimStart", trim_start::<Parser>),
+    ("trimEnd", trim_end::<Parser>),
+    ("toUpperCase", to_upper_case::<Parser>),
+    ("toLowerCase", to_lower_case::<Parser>),
+    ("startsWith", starts_with::<Parser>),
+    ("endsWith", ends_with::<Parser>),
+    ("repeat", repeat::<Parser>),
+    ("padStart", pad_start::<Parser>),
+    ("padEnd", pad_end::<Parser>),
+    ("charAt", char_at::<Parser>),
+    ("codePointAt", code_point_at::<Parser>),
+    ("includes", includes::<Parser>),
+  ];
+  for (name, func) in methods {
+    handler.add_native(name, NativeFunc::from((string, func, false)));
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, method, show, var};
+  use super::*;
+  #[test]
+  fn methods_count_characters_not_bytes() {
+    let handler = handler();
+    let run =
+      |this: &str, name: &str, args: Vec<Var>| method(&handler, var(this), name, args).to_string();
+    assert_eq!(run("\"héllo\"", "length", vec![]), "5");
+    assert_eq!(
+      run("\"héllo\"", "slice", vec![var("1"), var("3")]),
+      "\"él\""
+    );
+    assert_eq!(run("\"héllo\"", "slice", vec![Var::Integer(-2)]), "\"lo\"");
+    assert_eq!(
+      run("\"héllo\"", "substr", vec![var("1"), var("2")]),
+      "\"él\""
+    );
+    assert_eq!(run("\"héllo\"", "indexOf", vec![var("\"l\"")]), "2");
+    assert_eq!(
+      run("\"héllo\"", "indexOf", vec![var("\"l\""), var("3")]),
+      "3"
+    );
+    assert_eq!(run("\"héllo\"", "charAt", vec![var("1")]), "\"é\"");
+    assert_eq!(run("\"héllo\"", "codePointAt", vec![var("1")]), "233");
+    assert_eq!(run("\"héllo\"", "toUpperCase", vec![]), "\"HÉLLO\"");
+  }
+  #[test]
+  fn split_replace_trim_and_pad() {
+    let handler = handler();
+    let run =
+      |this: &str, name: &str, args: Vec<Var>| method(&handler, var(this), name, args).to_string();
+    assert_eq!(
+      run("\"a,b,,c\"", "split", vec![var("\",\"")]),
+      "[\"a\",\"b\",\"\",\"c\"]"
+    );
+    assert_eq!(
+      run("\"abc\"", "split", vec![var("\"\"")]),
+      "[\"a\",\"b\",\"c\"]"
+    );
+    assert_eq!(
+      run("\"aXbX\"", "replace", vec![var("\"X\""), var("\"-\"")]),
+      "\"a-bX\""
+    );
+    assert_eq!(
+      run("\"aXbX\"", "replaceAll", vec![var("\"X\""), var("\"-\"")]),
+      "\"a-b-\""
+    );
+    assert_eq!(run("\"  a b  \"", "trim", vec![]), "\"a b\"");
+    assert_eq!(run("\"  a\"", "trimStart", vec![]), "\"a\"");
+    assert_eq!(
+      run("\"7\"", "padStart", vec![var("3"), var("\"0\"")]),
+      "\"007\""
+    );
+    assert_eq!(run("\"7\"", "padEnd", vec![var("3")]), "\"7  \"");
+    assert_eq!(run("\"ab\"", "repeat", vec![var("3")]), "\"ababab\"");
+    assert_eq!(run("\"abc\"", "startsWith", vec![var("\"ab\"")]), "true");
+    assert_eq!(run("\"abc\"", "endsWith", vec![var("\"ab\"")]), "false");
+    assert_eq!(run("\"abc\"", "includes", vec![var("\"bc\"")]), "true");
+    assert_eq!(
+      call(
+        &handler,
+        "String.fromCodePoint",
+        vec![var("104"), var("105")]
+      )
+      .to_string(),
+      "\"hi\""
+    );
+    let huge = handler.call_native(
+      "repeat",
+      Rc::new(RefCell::new(var("\"ab\""))),
+      vec![Var::Integer(MAX_LENGTH as i64)],
+    );
+    assert_eq!(show(huge), "error: Invalid string length");
+  }
+}
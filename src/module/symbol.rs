@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+/// 驻留的字符串，用于标识符与对象的属性名。
+/// 内容相同的字符串总是对应同一个 `Symbol`，比较与哈希只涉及一个指针。
+/// 驻留表只保存仍在使用的字符串，最后一个 `Symbol` 释放时从表中移除。
+/// ```
+/// let a = Symbol::from("name");
+/// assert_eq!(a, Symbol::from(String::from("name")));
+/// assert_eq!(&*a.as_str(), "name");
+/// ```
+#[derive(Clone)]
+pub struct Symbol(Rc<str>);
+thread_local! {
+  /// 驻留表持有每个字符串的一个引用。
+  static INTERNER: RefCell<HashSet<Rc<str>>> = RefCell::new(HashSet::new());
+}
+impl Symbol {
+  pub fn intern(str: &str) -> Symbol {
+    INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      if let Some(val) = interner.get(str) {
+        return Symbol(val.clone());
+      }
+      let val: Rc<str> = Rc::from(str);
+      interner.insert(val.clone());
+      Symbol(val)
+    })
+  }
+  /// 查找已驻留的字符串，不存在时返回 `None` 且不驻留。
+  /// 用于只读的查找：未驻留的名字不可能是任何对象的属性。
+  pub fn lookup(str: &str) -> Option<Symbol> {
+    INTERNER.with(|interner| interner.borrow().get(str).map(|val| Symbol(val.clone())))
+  }
+  pub fn as_str(&self) -> Rc<str> {
+    self.0.clone()
+  }
+}
+impl Drop for Symbol {
+  fn drop(&mut self) {
+    // 只剩驻留表与自身两个引用时，从驻留表中移除。线程结束时驻留表可能已经销毁
+    if Rc::strong_count(&self.0) == 2 {
+      let _ = INTERNER.try_with(|interner| {
+        if let Ok(mut interner) = interner.try_borrow_mut() {
+          interner.remove(&*self.0);
+        }
+      });
+    }
+  }
+}
+impl PartialEq for Symbol {
+  fn eq(&self, other: &Self) -> bool {
+    Rc::ptr_eq(&self.0, &other.0)
+  }
+}
+impl Eq for Symbol {}
+impl Hash for Symbol {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    (Rc::as_ptr(&self.0) as *const u8).hash(state)
+  }
+}
+/// 按内容排序，使遍历顺序不依赖驻留的先后。
+impl PartialOrd for Symbol {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+impl Ord for Symbol {
+  fn cmp(&self, other: &Self) -> Ordering {
+    self.0.cmp(&other.0)
+  }
+}
+impl fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    fmt::Debug::fmt(&*self.0, f)
+  }
+}
+impl From<&str> for Symbol {
+  fn from(val: &str) -> Self {
+    Symbol::intern(val)
+  }
+}
+impl From<&String> for Symbol {
+  fn from(val: &String) -> Self {
+    Symbol::intern(val)
+  }
+}
+impl From<String> for Symbol {
+  fn from(val: String) -> Self {
+    Symbol::intern(&val)
+  }
+}
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+  #[test]
+  fn equal_strings_share_one_symbol() {
+    let a = Symbol::from("awa_symbol_test");
+    let b = Symbol::from(String::from("awa_symbol_test"));
+    assert!(Rc::ptr_eq(&a.as_str(), &b.as_str()));
+    assert_eq!(Symbol::lookup("awa_symbol_test"), Some(a.clone()));
+    assert!(Symbol::from("a") < Symbol::from("b"));
+    assert_eq!(a.to_string(), "awa_symbol_test");
+  }
+  #[test]
+  fn unused_symbols_leave_the_table() {
+    assert_eq!(Symbol::lookup("qwq_symbol_test"), None);
+    let a = Symbol::from("qwq_symbol_test");
+    let b = a.clone();
+    drop(a);
+    assert!(Symbol::lookup("qwq_symbol_test").is_some());
+    drop(b);
+    assert_eq!(Symbol::lookup("qwq_symbol_test"), None);
+  }
+}
@@ -0,0 +1,74 @@
+//! 单元测试共用的解析器与 `Handler`。
+use super::lpp::{
+  CodeSplitInterface, Context, Handler, LppError, NextVal, ParserInterface, RetVal, Scope,
+};
+pub use super::parse::Lpp;
+use super::var::Var;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+impl ParserInterface for Lpp {
+  fn name(&self) -> &String {
+    Lpp::name(self)
+  }
+  fn args(&self) -> &String {
+    Lpp::args(self)
+  }
+  fn new() -> Self {
+    Lpp::new()
+  }
+  fn to_string(&self) -> String {
+    format!("{}", self)
+  }
+  fn parse(str: &str) -> Self {
+    Lpp::parse(str)
+  }
+}
+impl CodeSplitInterface for Handler<Lpp> {
+  fn code_split(str: &str) -> Vec<String> {
+    str
+      .lines()
+      .map(|item| item.trim().to_string())
+      .filter(|item| !item.is_empty())
+      .collect()
+  }
+}
+/// 注册了标准库、未授予任何能力的 `Handler`。
+pub fn handler() -> Handler<Lpp> {
+  Handler::from((
+    Context::from(Rc::new(RefCell::new(Scope::new()))),
+    BTreeMap::new(),
+    NextVal::new(),
+    BTreeMap::new(),
+  ))
+  .with_stdlib()
+}
+pub fn var(str: &str) -> Var {
+  match Var::parse(str) {
+    Ok(val) => val,
+    Err(err) => panic!("{}", err.err),
+  }
+}
+/// 结果的文本形式：抛出的值以 `throw: ` 开头，错误以 `error: ` 开头。
+pub fn show(result: Result<Var, LppError>) -> String {
+  match result {
+    Ok(val) => val.to_string(),
+    Err(LppError::UnexpectedReturn(RetVal::ThrowValue(val))) => format!("throw: {}", val),
+    Err(LppError::UnexpectedReturn(RetVal::RetValue(val))) => format!("return: {}", val),
+    Err(LppError::Error(err)) => format!("error: {}", err.err),
+  }
+}
+/// 调用内置函数，出错时 panic。
+pub fn call(handler: &Handler<Lpp>, name: &str, args: Vec<Var>) -> Var {
+  match handler.call_builtin(name, None, args) {
+    Ok(val) => val,
+    err => panic!("{} failed with {}", name, show(err)),
+  }
+}
+/// 以 `this` 为接收者调用原生函数，出错时 panic。
+pub fn method(handler: &Handler<Lpp>, this: Var, name: &str, args: Vec<Var>) -> Var {
+  match handler.call_native(name, Rc::new(RefCell::new(this)), args) {
+    Ok(val) => val,
+    err => panic!("{} failed with {}", name, show(err)),
+  }
+}
@@ -0,0 +1,449 @@
+use super::error::Error;
+use super::lpp::{BuiltinFunc, CodeSplitInterface, Handler, LppError, NativeFunc, ParserInterface};
+use super::permission::Capability;
+use super::var::{ValueType, Var};
+use chrono::{
+  DateTime, FixedOffset, Months, NaiveDate, NaiveDateTime, SecondsFormat, TimeDelta, Utc,
+};
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+use std::rc::Rc;
+/// 时钟。`Handler` 通过它获取当前时间。
+pub trait Clock {
+  fn now(&self) -> DateTime<Utc>;
+}
+/// 系统时钟。
+pub struct SystemClock;
+impl Clock for SystemClock {
+  fn now(&self) -> DateTime<Utc> {
+    Utc::now()
+  }
+}
+/// 固定时钟。只有调用 `set` 或 `advance` 时时间才会改变，可用于测试。
+/// ```
+/// let clock = Rc::new(FixedClock::from(DateTime::UNIX_EPOCH));
+/// handler.clock = clock.clone();
+/// clock.advance(TimeDelta::seconds(1));
+/// ```
+pub struct FixedClock {
+  now: Cell<DateTime<Utc>>,
+}
+impl From<DateTime<Utc>> for FixedClock {
+  fn from(val: DateTime<Utc>) -> Self {
+    FixedClock {
+      now: Cell::new(val),
+    }
+  }
+}
+impl FixedClock {
+  pub fn set(&self, val: DateTime<Utc>) {
+    self.now.set(val);
+  }
+  pub fn advance(&self, val: TimeDelta) {
+    self.now.set(self.now.get() + val);
+  }
+}
+impl Clock for FixedClock {
+  fn now(&self) -> DateTime<Utc> {
+    self.now.get()
+  }
+}
+/// 解析 RFC 3339 格式的时间，如 `2024-01-01T08:00:00+08:00`。
+pub fn parse(str: &str) -> Result<DateTime<FixedOffset>, Error> {
+  match DateTime::parse_from_rfc3339(str.trim()) {
+    Ok(val) => Ok(val),
+    Err(_) => Err(Error::new(format!("Invalid date: {}", str))),
+  }
+}
+/// 按 strftime 格式解析时间。格式中不含时区时按 UTC 解析，不含时刻时取零点。
+/// ```
+/// let a = time::parse_format("2024-01-01", "%Y-%m-%d").unwrap();
+/// assert_eq!(time::format(&a, None).unwrap(), "2024-01-01T00:00:00Z");
+/// ```
+pub fn parse_format(str: &str, fmt: &str) -> Result<DateTime<FixedOffset>, Error> {
+  if let Ok(val) = DateTime::parse_from_str(str, fmt) {
+    return Ok(val);
+  }
+  if let Ok(val) = NaiveDateTime::parse_from_str(str, fmt) {
+    return Ok(val.and_utc().fixed_offset());
+  }
+  match NaiveDate::parse_from_str(str, fmt) {
+    Ok(val) => Ok(val.and_time(Default::default()).and_utc().fixed_offset()),
+    Err(_) => Err(Error::new(format!("Invalid date: {}", str))),
+  }
+}
+/// 格式化时间。`fmt` 为 `None` 时输出 RFC 3339 格式，否则按 strftime 格式输出。
+pub fn format(val: &DateTime<FixedOffset>, fmt: Option<&str>) -> Result<String, Error> {
+  match fmt {
+    None => Ok(val.to_rfc3339_opts(SecondsFormat::AutoSi, true)),
+    Some(fmt) => {
+      let mut ret = String::new();
+      match write!(ret, "{}", val.format(fmt)) {
+        Ok(_) => Ok(ret),
+        Err(_) => Err(Error::new(format!("Invalid time format: {}", fmt))),
+      }
+    }
+  }
+}
+/// 解析 ISO 8601 格式的时长，如 `PT1.5S`、`P1DT2H`、`-PT30M`，即 `TimeDelta` 的输出格式。
+/// 支持 `W`、`D`、`H`、`M`、`S`，只有秒可以带小数。不支持长度不固定的年与月。
+/// ```
+/// let a = time::parse_duration("P1DT1.5S").unwrap();
+/// assert_eq!(a, TimeDelta::milliseconds(86_401_500));
+/// ```
+pub fn parse_duration(str: &str) -> Result<TimeDelta, Error> {
+  let invalid = || Error::new(format!("Invalid duration: {}", str));
+  let text = str.trim();
+  let (negative, text) = match text.strip_prefix('-') {
+    Some(text) => (true, text),
+    None => (false, text),
+  };
+  let text = text.strip_prefix('P').ok_or_else(invalid)?;
+  let mut nanos: i128 = 0;
+  let mut number = String::new();
+  let mut time = false;
+  // 下一个单位的最小序号，保证单位按 `W D H M S` 的顺序出现且不重复
+  let mut next = 0;
+  for item in text.chars() {
+    match item {
+      '0'..='9' | '.' => number.push(item),
+      'T' if !time && number.is_empty() => {
+        time = true;
+        next = 2;
+      }
+      unit => {
+        let (rank, secs) = match (time, unit) {
+          (false, 'W') => (0, 604_800),
+          (false, 'D') => (1, 86_400),
+          (true, 'H') => (2, 3_600),
+          (true, 'M') => (3, 60),
+          (true, 'S') => (4, 1),
+          _ => return Err(invalid()),
+        };
+        if rank < next {
+          return Err(invalid());
+        }
+        next = rank + 1;
+        let (int, frac) = match number.split_once('.') {
+          Some((int, frac)) if unit == 'S' && !frac.is_empty() && frac.len() <= 9 => (int, frac),
+          Some(_) => return Err(invalid()),
+          None => (number.as_str(), ""),
+        };
+        let int: i128 = int.parse().map_err(|_| invalid())?;
+        let frac: i128 = format!("{:0<9}", frac).parse().map_err(|_| invalid())?;
+        nanos = int
+          .checked_mul(secs * 1_000_000_000)
+          .and_then(|val| val.checked_add(frac))
+          .and_then(|val| nanos.checked_add(val))
+          .ok_or_else(|| Error::from("Duration overflow"))?;
+        number.clear();
+      }
+    }
+  }
+  // 没有任何单位，或 `T` 之后没有时刻部分
+  if !number.is_empty() || next == 0 || (time && next == 2) {
+    return Err(invalid());
+  }
+  from_nanos(if negative { -nanos } else { nanos }).ok_or_else(|| Error::from("Duration overflow"))
+}
+/// 将时长换算为纳秒。
+pub fn to_nanos(val: &TimeDelta) -> i128 {
+  val.num_seconds() as i128 * 1_000_000_000 + val.subsec_nanos() as i128
+}
+/// 由纳秒数构造时长。超出范围时返回 `None`。
+pub fn from_nanos(val: i128) -> Option<TimeDelta> {
+  let secs = i64::try_from(val.div_euclid(1_000_000_000)).ok()?;
+  TimeDelta::new(secs, val.rem_euclid(1_000_000_000) as u32)
+}
+fn from_nanos_f64(val: f64) -> Result<TimeDelta, Error> {
+  if val.is_finite() && val.abs() < i128::MAX as f64 {
+    if let Some(val) = from_nanos(val.round() as i128) {
+      return Ok(val);
+    }
+  }
+  Err(Error::from("Duration overflow"))
+}
+/// 将时长乘以 `Integer` 或 `Number`。`Integer` 的结果是精确的。
+pub fn mul(val: &TimeDelta, factor: &Var) -> Result<TimeDelta, Error> {
+  match factor {
+    Var::Integer(factor) => match to_nanos(val).checked_mul(*factor as i128) {
+      Some(nanos) => from_nanos(nanos).ok_or_else(|| Error::from("Duration overflow")),
+      None => Err(Error::from("Duration overflow")),
+    },
+    Var::Number(factor) => from_nanos_f64(to_nanos(val) as f64 * factor),
+    _ => Err(Error::from("Calculation failed")),
+  }
+}
+/// 将时长除以 `Integer`、`Number` 或另一个时长。
+/// 除以时长时得到二者之比（`Number`），否则得到时长。
+pub fn div(val: &TimeDelta, divisor: &Var) -> Result<Var, Error> {
+  let nanos = to_nanos(val);
+  match divisor {
+    Var::Integer(0) => Err(Error::from("Division by zero")),
+    Var::Integer(divisor) => match from_nanos(nanos / *divisor as i128) {
+      Some(val) => Ok(Var::Duration(val)),
+      None => Err(Error::from("Duration overflow")),
+    },
+    Var::Number(divisor) if *divisor == 0.0 => Err(Error::from("Division by zero")),
+    Var::Number(divisor) => Ok(Var::Duration(from_nanos_f64(nanos as f64 / divisor)?)),
+    Var::Duration(divisor) if divisor.is_zero() => Err(Error::from("Division by zero")),
+    Var::Duration(divisor) => Ok(Var::Number(nanos as f64 / to_nanos(divisor) as f64)),
+    _ => Err(Error::from("Calculation failed")),
+  }
+}
+fn get_time(args: &[Var], index: usize) -> Result<DateTime<FixedOffset>, LppError> {
+  match args.get(index) {
+    Some(Var::DateTime(val)) => Ok(*val),
+    _ => Err(LppError::Error(Error::from("DateTime expected"))),
+  }
+}
+fn get_duration(args: &[Var], index: usize) -> Result<TimeDelta, LppError> {
+  match args.get(index) {
+    Some(Var::Duration(val)) => Ok(*val),
+    _ => Err(LppError::Error(Error::from("Duration expected"))),
+  }
+}
+fn get_format(args: &[Var], index: usize) -> Result<Option<String>, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(None),
+    Some(Var::String(val)) => Ok(Some(val.clone())),
+    _ => Err(LppError::Error(Error::from("Format string expected"))),
+  }
+}
+fn get_integer(args: &[Var], index: usize) -> Result<i64, LppError> {
+  match args.get(index) {
+    Some(val) => match val.clone().convert(ValueType::Integer) {
+      Ok(Var::Integer(val)) => Ok(val),
+      _ => Err(LppError::Error(Error::from("Integer expected"))),
+    },
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+fn date_now<Parser>(
+  handler: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  _: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::DateTime(handler.clock.now().fixed_offset()))
+}
+fn date_parse<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let str = match args.first() {
+    Some(Var::String(val)) => val.clone(),
+    _ => return Err(LppError::Error(Error::from("Date.parse expects a string"))),
+  };
+  match get_format(&args, 1)? {
+    Some(fmt) => parse_format(str.as_str(), fmt.as_str()),
+    None => parse(str.as_str()),
+  }
+  .map(Var::DateTime)
+  .map_err(LppError::Error)
+}
+fn date_format<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = get_time(&args, 0)?;
+  Ok(Var::String(
+    format(&val, get_format(&args, 1)?.as_deref()).map_err(LppError::Error)?,
+  ))
+}
+fn date_from_timestamp<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match DateTime::from_timestamp_millis(get_integer(&args, 0)?) {
+    Some(val) => Ok(Var::DateTime(val.fixed_offset())),
+    None => Err(LppError::Error(Error::from("Timestamp out of range"))),
+  }
+}
+fn date_timestamp<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(get_time(&args, 0)?.timestamp_millis()))
+}
+fn date_add_months<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  let val = get_time(&args, 0)?;
+  let months = get_integer(&args, 1)?;
+  let ret = match u32::try_from(months.unsigned_abs()) {
+    Ok(abs) if months >= 0 => val.checked_add_months(Months::new(abs)),
+    Ok(abs) => val.checked_sub_months(Months::new(abs)),
+    Err(_) => None,
+  };
+  match ret {
+    Some(val) => Ok(Var::DateTime(val)),
+    None => Err(LppError::Error(Error::from("Date out of range"))),
+  }
+}
+fn duration_parse<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  match args.first() {
+    Some(Var::String(val)) => parse_duration(val)
+      .map(Var::Duration)
+      .map_err(LppError::Error),
+    _ => Err(LppError::Error(Error::from(
+      "Duration.parse expects a string",
+    ))),
+  }
+}
+fn duration_of(args: &[Var], unit: TimeDelta) -> Result<Var, LppError> {
+  match args.first() {
+    Some(val) => Ok(Var::Duration(mul(&unit, val).map_err(LppError::Error)?)),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+fn duration_days<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  duration_of(&args, TimeDelta::days(1))
+}
+fn duration_hours<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  duration_of(&args, TimeDelta::hours(1))
+}
+fn duration_minutes<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  duration_of(&args, TimeDelta::minutes(1))
+}
+fn duration_seconds<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  duration_of(&args, TimeDelta::seconds(1))
+}
+fn duration_milliseconds<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  duration_of(&args, TimeDelta::milliseconds(1))
+}
+fn duration_to_seconds<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Number(
+    to_nanos(&get_duration(&args, 0)?) as f64 / 1_000_000_000.0,
+  ))
+}
+fn duration_to_milliseconds<Parser>(
+  _: &Handler<Parser>,
+  _: Option<Rc<RefCell<Var>>>,
+  args: Vec<Var>,
+) -> Result<Var, LppError> {
+  Ok(Var::Integer(get_duration(&args, 0)?.num_milliseconds()))
+}
+/// 在 `Handler` 中注册 `Date.*` 与 `Duration.*`。
+/// `Date.now` 从 `Handler::clock` 读取当前时间，需要 `Capability::Clock`。
+pub fn register<Parser: ParserInterface>(handler: &mut Handler<Parser>)
+where
+  Handler<Parser>: CodeSplitInterface,
+{
+  handler.builtin.insert(
+    String::from("Date.now"),
+    NativeFunc::from(date_now::<Parser> as BuiltinFunc<Parser>).requires(Capability::Clock),
+  );
+  handler.add_builtin("Date.parse", date_parse::<Parser>);
+  handler.add_builtin("Date.format", date_format::<Parser>);
+  handler.add_builtin("Date.fromTimestamp", date_from_timestamp::<Parser>);
+  handler.add_builtin("Date.timestamp", date_timestamp::<Parser>);
+  handler.add_builtin("Date.addMonths", date_add_months::<Parser>);
+  handler.add_builtin("Duration.parse", duration_parse::<Parser>);
+  handler.add_builtin("Duration.days", duration_days::<Parser>);
+  handler.add_builtin("Duration.hours", duration_hours::<Parser>);
+  handler.add_builtin("Duration.minutes", duration_minutes::<Parser>);
+  handler.add_builtin("Duration.seconds", duration_seconds::<Parser>);
+  handler.add_builtin("Duration.milliseconds", duration_milliseconds::<Parser>);
+  handler.add_builtin("Duration.toSeconds", duration_to_seconds::<Parser>);
+  handler.add_builtin(
+    "Duration.toMilliseconds",
+    duration_to_milliseconds::<Parser>,
+  );
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, var};
+  use super::*;
+  #[test]
+  fn parses_iso_durations() {
+    let nanos = |str: &str| parse_duration(str).map(|val| to_nanos(&val)).ok();
+    assert_eq!(nanos("PT1S"), Some(1_000_000_000));
+    assert_eq!(nanos("P0D"), Some(0));
+    assert_eq!(nanos("-PT1.5S"), Some(-1_500_000_000));
+    assert_eq!(
+      nanos("P1W2DT3H4M5.000000001S"),
+      Some((((9 * 24 + 3) * 60 + 4) * 60 + 5) * 1_000_000_000 + 1)
+    );
+    for str in [
+      "", "P", "PT", "1S", "P1H", "PT1D", "PT1S2M", "P1D1W", "PT1.S", "P1.5D", "P1Y",
+    ] {
+      assert_eq!(nanos(str), None, "{}", str);
+    }
+  }
+  #[test]
+  fn durations_round_trip_through_to_string() {
+    for val in [
+      TimeDelta::seconds(1),
+      TimeDelta::milliseconds(-1500),
+      TimeDelta::zero(),
+      TimeDelta::days(1) + TimeDelta::nanoseconds(1),
+    ] {
+      let text = Var::Duration(val).to_string();
+      assert!(
+        matches!(Var::parse(&text), Ok(Var::Duration(ret)) if ret == val),
+        "{}",
+        text
+      );
+      let text = Var::Duration(val).convert(ValueType::String).ok().unwrap();
+      assert!(matches!(text.convert(ValueType::Duration), Ok(Var::Duration(ret)) if ret == val));
+    }
+    assert_eq!(
+      Var::Duration(TimeDelta::seconds(1)).to_string(),
+      "t\"PT1S\""
+    );
+  }
+  #[test]
+  fn frozen_clock_drives_date_now() {
+    let mut handler = handler();
+    let clock = Rc::new(FixedClock::from(DateTime::UNIX_EPOCH));
+    handler.clock = clock.clone();
+    handler.permissions.grant(Capability::Clock);
+    assert_eq!(
+      call(&handler, "Date.now", vec![]).to_string(),
+      "d\"1970-01-01T00:00:00Z\""
+    );
+    clock.advance(parse_duration("PT1S").ok().unwrap());
+    let now = call(&handler, "Date.now", vec![]);
+    assert_eq!(now.to_string(), "d\"1970-01-01T00:00:01Z\"");
+    let later = now.opcall("+", &var("t\"PT1H\"")).ok().unwrap();
+    assert_eq!(later.to_string(), "d\"1970-01-01T01:00:01Z\"");
+    assert_eq!(
+      call(&handler, "Duration.parse", vec![var("\"PT90M\"")]).to_string(),
+      "t\"PT5400S\""
+    );
+  }
+}
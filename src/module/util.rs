@@ -0,0 +1,60 @@
+use super::error::Error;
+use super::lpp::{LppError, RetVal};
+use super::var::{FuncValue, ValueType, Var};
+use std::cell::RefCell;
+use std::rc::Rc;
+/// 以 `ThrowValue` 抛出原因，脚本可以捕获。
+pub fn throw(reason: String) -> LppError {
+  LppError::UnexpectedReturn(RetVal::ThrowValue(Var::String(reason)))
+}
+pub fn cell(val: Var) -> Rc<RefCell<Var>> {
+  Rc::new(RefCell::new(val))
+}
+pub fn array(val: Vec<Var>) -> Var {
+  Var::from(val.into_iter().map(cell).collect::<Vec<Rc<RefCell<Var>>>>())
+}
+/// 把下标换算为 `0..=len` 中的位置，负数从末尾开始计数。
+pub fn position(index: i64, len: usize) -> usize {
+  if index < 0 {
+    len.saturating_sub(index.unsigned_abs() as usize)
+  } else {
+    (index as usize).min(len)
+  }
+}
+/// 方法的接收者。
+pub fn receiver(this: Option<Rc<RefCell<Var>>>) -> Result<Rc<RefCell<Var>>, LppError> {
+  match this {
+    Some(this) => Ok(this),
+    None => Err(LppError::Error(Error::from(
+      "Method called without receiver",
+    ))),
+  }
+}
+/// 接收者的类型与方法不符。
+pub fn type_error() -> LppError {
+  LppError::Error(Error::from("Receiver type mismatch"))
+}
+pub fn get_string(args: &[Var], index: usize) -> Result<String, LppError> {
+  match args.get(index) {
+    Some(Var::String(val)) => Ok(val.clone()),
+    Some(_) => Err(LppError::Error(Error::from("String expected"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
+/// 可选的整数参数，省略或为 `null` 时返回 `None`。
+pub fn get_integer(args: &[Var], index: usize) -> Result<Option<i64>, LppError> {
+  match args.get(index) {
+    None | Some(Var::Null(_)) => Ok(None),
+    Some(val) => match val.clone().convert(ValueType::Integer) {
+      Ok(Var::Integer(val)) => Ok(Some(val)),
+      _ => Err(LppError::Error(Error::from("Integer expected"))),
+    },
+  }
+}
+pub fn get_callback(args: &[Var], index: usize) -> Result<FuncValue, LppError> {
+  match args.get(index) {
+    Some(Var::Function(val)) => Ok(val.clone()),
+    Some(_) => Err(LppError::Error(Error::from("Function expected"))),
+    None => Err(LppError::Error(Error::from("Too few arguments given"))),
+  }
+}
@@ -1,10 +1,22 @@
+use super::bytes;
 use super::error::Error;
+use super::gc;
+use super::number;
 use super::parse::{transfer, LppStatus, QuoteStatus};
+use super::pattern::{self, RegexValue};
+use super::symbol::Symbol;
+use super::time;
+use chrono::{DateTime, FixedOffset, TimeDelta};
+use indexmap::{IndexMap, IndexSet};
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
 use parse_int;
+use rust_decimal::{Decimal, RoundingStrategy};
 use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
-use std::i32;
+use std::fmt;
 use std::rc::Rc;
 /// 语句块。
 /// 你可以以以下方式定义一个语句块：
@@ -22,6 +34,11 @@ pub struct StmtValue {
   /// 保存原始内容（含有空格，分隔符等），需要手动分割。
   pub value: String,
 }
+impl Default for StmtValue {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl StmtValue {
   /// 新建一个语句块。
   /// 若需要指定语句块内容，请使用 `StmtValue::from` 而不是 `StmtValue::new`。
@@ -59,14 +76,14 @@ impl StmtValue {
     }
   }
 }
-impl ToString for StmtValue {
+impl fmt::Display for StmtValue {
   /// 对语句块进行序列化。
   /// ```
   /// let a = StmtValue::parse("{awa}");
   /// assert_eq!(a.to_string(), "{awa}");
   /// ```
-  fn to_string(&self) -> String {
-    format!("{{{}}}", self.value)
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{{{}}}", self.value)
   }
 }
 /// 单个参数。
@@ -85,6 +102,11 @@ pub struct ArgItem {
   /// 没有参数时，`value`应为空字符串。
   pub value: String,
 }
+impl Default for ArgItem {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl ArgItem {
   /// 新建参数。
   /// 不推荐使用此方法。请换用 `ArgItem::from` 来新建参数。
@@ -100,18 +122,18 @@ impl ArgItem {
     }
   }
 }
-impl ToString for ArgItem {
+impl fmt::Display for ArgItem {
   /// 序列化参数。
   /// 序列化的参数将可以被 `ArgItem::parse` 解析。
   /// ```
   /// let a = ArgItem::from((String::from("awa"),String::from("1")));
   /// assert_eq!(a.to_string(), "awa=1");
   /// ```
-  fn to_string(&self) -> String {
-    if self.value != "" {
-      format!("{}={}", self.name, self.value)
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    if !self.value.is_empty() {
+      write!(f, "{}={}", self.name, self.value)
     } else {
-      self.name.clone()
+      f.write_str(&self.name)
     }
   }
 }
@@ -167,6 +189,11 @@ pub struct FuncValue {
   /// 语句块。
   pub value: StmtValue,
 }
+impl Default for FuncValue {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl FuncValue {
   /// 创建空的函数。
   /// ```
@@ -188,10 +215,10 @@ impl TryFrom<(Vec<ArgItem>, StmtValue)> for FuncValue {
   fn try_from(val: (Vec<ArgItem>, StmtValue)) -> Result<Self, Self::Error> {
     let mut flag = false;
     for item in val.0.iter() {
-      if item.value == "" && flag {
+      if item.value.is_empty() && flag {
         return Err(Error::from("Syntax Error"));
       }
-      if item.value != "" {
+      if !item.value.is_empty() {
         flag = true;
       }
     }
@@ -201,17 +228,16 @@ impl TryFrom<(Vec<ArgItem>, StmtValue)> for FuncValue {
     })
   }
 }
-impl ToString for FuncValue {
-  fn to_string(&self) -> String {
-    let mut tmp = String::from("func(");
+impl fmt::Display for FuncValue {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("func(")?;
     for (index, item) in self.args.iter().enumerate() {
-      tmp += item.to_string().as_str();
+      write!(f, "{}", item)?;
       if index + 1 < self.args.len() {
-        tmp.push(',')
+        f.write_str(",")?;
       }
     }
-    tmp.push(')');
-    format!("{}{}", tmp, self.value.to_string())
+    write!(f, "){}", self.value)
   }
 }
 impl FuncValue {
@@ -236,7 +262,7 @@ impl FuncValue {
             temp.push(item);
           }
         }
-        if temp != "" {
+        if !temp.is_empty() {
           arg.push(ArgItem::parse(temp.as_str()));
         }
         nowindex += 1;
@@ -259,10 +285,7 @@ impl FuncValue {
   }
 }
 pub fn covered_with(str: &str, left: char, right: char) -> bool {
-  if utf8_slice::len(str) < 2
-    || str.chars().nth(0) != Some(left)
-    || str.chars().nth_back(0) != Some(right)
-  {
+  if utf8_slice::len(str) < 2 || !str.starts_with(left) || str.chars().nth_back(0) != Some(right) {
     return false;
   }
   let mut status = LppStatus::new();
@@ -287,7 +310,7 @@ pub fn split_by(str: &str, delim: char) -> Vec<String> {
       tmp.push(item);
     }
   }
-  if tmp != "" {
+  if !tmp.is_empty() {
     ret.push(tmp);
   }
   ret
@@ -321,7 +344,7 @@ pub fn clearnull(str: &str) -> String {
     }
     tmp.push(item);
   }
-  return tmp;
+  tmp
 }
 #[derive(Clone)]
 pub enum ExprValue {
@@ -329,6 +352,11 @@ pub enum ExprValue {
   Expr((String, String, String)),
   Val(String),
 }
+impl Default for ExprValue {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl ExprValue {
   pub fn new() -> Self {
     ExprValue::Val(String::new())
@@ -387,11 +415,7 @@ impl ExprValue {
     }
     match ExprValue::parse(clearnull(str).as_str()) {
       Ok(p) => {
-        if let ExprValue::Expr(_) = p {
-          true
-        } else {
-          false
-        }
+        matches!(p, ExprValue::Expr(_))
       }
       Err(_) => false,
     }
@@ -405,8 +429,13 @@ impl ExprValue {
     let mut temp = String::new();
     let mut front = true;
     let mut minpr = i32::MAX;
+    let mut operand = String::new();
     for (index, item) in str.chars().enumerate() {
       if status.brace == 0 && status.quote == QuoteStatus::None {
+        if (item == '+' || item == '-') && temp.is_empty() && ExprValue::is_mantissa(&operand) {
+          operand.push(item);
+          continue;
+        }
         if ExprValue::getprio(format!("{}{}", temp, item).as_str(), front) == -1 {
           let c = ExprValue::getprio(temp.as_str(), front);
           if c != -1 {
@@ -420,8 +449,10 @@ impl ExprValue {
         }
         if ExprValue::getprio(format!("{}{}", temp, item).as_str(), front) != -1 {
           temp.push(item);
+          operand.clear();
         } else {
           front = false;
+          operand.push(item);
         }
       }
       transfer(item, &mut status);
@@ -441,10 +472,23 @@ impl ExprValue {
     Ok(ExprValue::from((
       utf8_slice::slice(str, opindex, opend).to_string(),
       utf8_slice::slice(str, 0, opindex).to_string(),
-      utf8_slice::slice(str, opend + 1, utf8_slice::len(str)).to_string(),
+      utf8_slice::slice(str, opend, utf8_slice::len(str)).to_string(),
     )))
   }
 }
+impl ExprValue {
+  /// 判断 `str` 是否为以 `e` 结尾的十进制数字，其后的 `+`、`-` 属于指数而不是运算符。
+  fn is_mantissa(str: &str) -> bool {
+    match str.strip_suffix('e') {
+      Some(val) => {
+        val.starts_with(|item: char| item.is_ascii_digit())
+          && val.chars().all(|item| item.is_ascii_digit() || item == '.')
+          && val.matches('.').count() <= 1
+      }
+      None => false,
+    }
+  }
+}
 impl From<(String, String, String)> for ExprValue {
   fn from(val: (String, String, String)) -> Self {
     ExprValue::Expr(val)
@@ -455,14 +499,164 @@ impl From<String> for ExprValue {
     ExprValue::Val(val)
   }
 }
+/// `Decimal` 运算的精度与舍入方式。
+/// 每次 `Decimal` 运算的结果都会被舍入到 `scale` 位小数。
+/// ```
+/// DecimalContext::set(DecimalContext {
+///   scale: 2,
+///   rounding: RoundingStrategy::MidpointAwayFromZero,
+/// });
+/// let a = Var::parse("10m").unwrap().opcall("/", &Var::parse("3m").unwrap()).unwrap();
+/// assert_eq!(a.to_string(), "3.33m");
+/// ```
+#[derive(Clone, Copy)]
+pub struct DecimalContext {
+  /// 保留的小数位数，最大为 28。
+  pub scale: u32,
+  /// 舍入方式。
+  pub rounding: RoundingStrategy,
+}
+thread_local! {
+  static DECIMAL_CONTEXT: RefCell<DecimalContext> = RefCell::new(DecimalContext::new());
+  /// `opcmp` 的嵌套深度，以及比较过程中假定相等的容器对。
+  static EQUAL_ASSUMED: RefCell<(usize, HashSet<(usize, usize)>)> =
+    RefCell::new((0, HashSet::new()));
+}
+impl Default for DecimalContext {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+impl DecimalContext {
+  /// 默认保留 28 位小数，并使用银行家舍入。
+  pub fn new() -> Self {
+    DecimalContext {
+      scale: 28,
+      rounding: RoundingStrategy::MidpointNearestEven,
+    }
+  }
+  /// 获取当前线程的 `DecimalContext`。
+  pub fn get() -> Self {
+    DECIMAL_CONTEXT.with(|ctx| *ctx.borrow())
+  }
+  /// 设置当前线程的 `DecimalContext`。
+  pub fn set(ctx: DecimalContext) {
+    DECIMAL_CONTEXT.with(|val| *val.borrow_mut() = ctx);
+  }
+  pub fn round(&self, val: Decimal) -> Decimal {
+    val.round_dp_with_strategy(self.scale, self.rounding)
+  }
+}
+/// `Map` 的键与 `Set` 的元素。
+/// 键按值保存：数组会被逐项复制，之后对原数组的修改不会影响键。
+/// 键的相等性与 `===` 一致，即 `1` 与 `1.0` 是不同的键。
+/// ```
+/// let a = VarKey::try_from(&Var::parse("[1,\"awa\"]").unwrap()).unwrap();
+/// assert_eq!(Var::from(&a).to_string(), "[1,\"awa\"]");
+/// ```
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum VarKey {
+  Null,
+  Boolean(bool),
+  /// 以位模式保存，`-0` 与 `0` 视为同一个键，所有 `NaN` 视为同一个键。
+  Number(u64),
+  Integer(i64),
+  BigInt(BigInt),
+  Decimal(Decimal),
+  String(String),
+  Bytes(Vec<u8>),
+  Array(Vec<VarKey>),
+}
+impl TryFrom<&Var> for VarKey {
+  type Error = Error;
+  fn try_from(val: &Var) -> Result<Self, Self::Error> {
+    VarKey::from_var(val, &mut vec![])
+  }
+}
+impl VarKey {
+  /// `path` 为正在转换的数组，用于发现循环引用。
+  fn from_var(val: &Var, path: &mut Vec<usize>) -> Result<Self, Error> {
+    match val {
+      Var::Null(_) => Ok(VarKey::Null),
+      Var::Boolean(val) => Ok(VarKey::Boolean(*val)),
+      Var::Number(val) => Ok(VarKey::Number(if *val == 0.0 {
+        0
+      } else if val.is_nan() {
+        f64::NAN.to_bits()
+      } else {
+        val.to_bits()
+      })),
+      Var::Integer(val) => Ok(VarKey::Integer(*val)),
+      Var::BigInt(val) => Ok(VarKey::BigInt(val.clone())),
+      Var::Decimal(val) => Ok(VarKey::Decimal(*val)),
+      Var::String(val) => Ok(VarKey::String(val.clone())),
+      Var::Bytes(val) => Ok(VarKey::Bytes(val.clone())),
+      Var::Array(val) => {
+        let id = Rc::as_ptr(val) as usize;
+        if path.contains(&id) {
+          return Err(Error::from("Unhashable key: circular array"));
+        }
+        path.push(id);
+        let mut ret: Vec<VarKey> = vec![];
+        for item in val.borrow().iter() {
+          ret.push(VarKey::from_var(&item.borrow(), path)?);
+        }
+        path.pop();
+        Ok(VarKey::Array(ret))
+      }
+      _ => Err(Error::from("Unhashable key")),
+    }
+  }
+}
+impl From<&VarKey> for Var {
+  fn from(val: &VarKey) -> Self {
+    match val {
+      VarKey::Null => Var::Null(()),
+      VarKey::Boolean(val) => Var::Boolean(*val),
+      VarKey::Number(val) => Var::Number(f64::from_bits(*val)),
+      VarKey::Integer(val) => Var::Integer(*val),
+      VarKey::BigInt(val) => Var::BigInt(val.clone()),
+      VarKey::Decimal(val) => Var::Decimal(*val),
+      VarKey::String(val) => Var::String(val.clone()),
+      VarKey::Bytes(val) => Var::Bytes(val.clone()),
+      VarKey::Array(val) => Var::from(
+        val
+          .iter()
+          .map(|item| Rc::new(RefCell::new(Var::from(item))))
+          .collect::<Vec<Rc<RefCell<Var>>>>(),
+      ),
+    }
+  }
+}
+/// 数组的存储。多个 `Var::Array` 可以共享同一个数组。
+pub type ArrayRef = Rc<RefCell<Vec<Rc<RefCell<Var>>>>>;
+/// 对象的存储。多个 `Var::Object` 可以共享同一个对象。
+/// 属性名以 `Symbol` 保存，形状相同的对象共享属性名的存储。
+pub type ObjectRef = Rc<RefCell<IndexMap<Symbol, Rc<RefCell<Var>>>>>;
+/// `Map` 的存储。多个 `Var::Map` 可以共享同一个 `Map`。
+pub type MapRef = Rc<RefCell<IndexMap<VarKey, Rc<RefCell<Var>>>>>;
+/// 值。
+/// `Array`、`Object` 与 `Map` 具有引用语义：`clone` 只复制引用，修改对所有引用可见。
+/// 需要独立的副本时请使用 `deep_clone`。
 pub enum Var {
   Null(()),
   Boolean(bool),
   Number(f64),
+  Integer(i64),
+  BigInt(BigInt),
+  Decimal(Decimal),
   String(String),
-  Array(Vec<Rc<RefCell<Var>>>),
-  Object(BTreeMap<String, Rc<RefCell<Var>>>),
+  Bytes(Vec<u8>),
+  DateTime(DateTime<FixedOffset>),
+  Duration(TimeDelta),
+  Regex(RegexValue),
+  Array(ArrayRef),
+  Object(ObjectRef),
+  Map(MapRef),
+  Set(IndexSet<VarKey>),
   Function(FuncValue),
+  /// 内置函数的引用，按 `Handler::builtin` 中注册的名称调用，类型视为 `Function`。
+  Builtin(String),
   Statement(StmtValue),
   Expression(ExprValue),
 }
@@ -471,9 +665,18 @@ pub enum ValueType {
   Null,
   Boolean,
   Number,
+  Integer,
+  BigInt,
+  Decimal,
   String,
+  Bytes,
+  DateTime,
+  Duration,
+  Regex,
   Array,
   Object,
+  Map,
+  Set,
   Function,
   Statement,
   Expression,
@@ -485,10 +688,19 @@ impl Var {
       Var::Null(_) => ValueType::Null,
       Var::Boolean(_) => ValueType::Boolean,
       Var::Number(_) => ValueType::Number,
+      Var::Integer(_) => ValueType::Integer,
+      Var::BigInt(_) => ValueType::BigInt,
+      Var::Decimal(_) => ValueType::Decimal,
       Var::String(_) => ValueType::String,
+      Var::Bytes(_) => ValueType::Bytes,
+      Var::DateTime(_) => ValueType::DateTime,
+      Var::Duration(_) => ValueType::Duration,
+      Var::Regex(_) => ValueType::Regex,
       Var::Array(_) => ValueType::Array,
       Var::Object(_) => ValueType::Object,
-      Var::Function(_) => ValueType::Function,
+      Var::Map(_) => ValueType::Map,
+      Var::Set(_) => ValueType::Set,
+      Var::Function(_) | Var::Builtin(_) => ValueType::Function,
       Var::Statement(_) => ValueType::Statement,
       Var::Expression(_) => ValueType::Expression,
     }
@@ -500,41 +712,148 @@ impl Clone for Var {
       Var::Null(_) => Var::Null(()),
       Var::Boolean(v) => Var::Boolean(*v),
       Var::Number(v) => Var::Number(*v),
+      Var::Integer(v) => Var::Integer(*v),
+      Var::BigInt(v) => Var::BigInt(v.clone()),
+      Var::Decimal(v) => Var::Decimal(*v),
       Var::String(v) => Var::String(v.clone()),
-      Var::Array(v) => {
-        let mut a = vec![];
-        for item in v.iter() {
-          a.push(Rc::new(RefCell::new(item.borrow().clone())));
+      Var::Bytes(v) => Var::Bytes(v.clone()),
+      Var::DateTime(v) => Var::DateTime(*v),
+      Var::Duration(v) => Var::Duration(*v),
+      Var::Regex(v) => Var::Regex(v.clone()),
+      Var::Array(v) => Var::Array(v.clone()),
+      Var::Object(v) => Var::Object(v.clone()),
+      Var::Map(v) => Var::Map(v.clone()),
+      Var::Set(v) => Var::Set(v.clone()),
+      Var::Function(v) => Var::Function(v.clone()),
+      Var::Builtin(v) => Var::Builtin(v.clone()),
+      Var::Statement(v) => Var::Statement(v.clone()),
+      Var::Expression(v) => Var::Expression(v.clone()),
+    }
+  }
+}
+impl From<Vec<Rc<RefCell<Var>>>> for Var {
+  /// 新建的数组将由 `gc` 追踪。
+  fn from(val: Vec<Rc<RefCell<Var>>>) -> Self {
+    let ret = Rc::new(RefCell::new(val));
+    gc::track_array(&ret);
+    Var::Array(ret)
+  }
+}
+impl From<IndexMap<Symbol, Rc<RefCell<Var>>>> for Var {
+  /// 新建的对象将由 `gc` 追踪。
+  fn from(val: IndexMap<Symbol, Rc<RefCell<Var>>>) -> Self {
+    let ret = Rc::new(RefCell::new(val));
+    gc::track_object(&ret);
+    Var::Object(ret)
+  }
+}
+impl From<IndexMap<VarKey, Rc<RefCell<Var>>>> for Var {
+  /// 新建的 `Map` 将由 `gc` 追踪。
+  fn from(val: IndexMap<VarKey, Rc<RefCell<Var>>>) -> Self {
+    let ret = Rc::new(RefCell::new(val));
+    gc::track_map(&ret);
+    Var::Map(ret)
+  }
+}
+impl Var {
+  /// 返回数组、对象或 `Map` 存储的地址，用于判断两个值是否引用同一个容器。其他类型返回 `None`。
+  pub fn container_id(&self) -> Option<usize> {
+    match self {
+      Var::Array(val) => Some(Rc::as_ptr(val) as *const () as usize),
+      Var::Object(val) => Some(Rc::as_ptr(val) as *const () as usize),
+      Var::Map(val) => Some(Rc::as_ptr(val) as *const () as usize),
+      _ => None,
+    }
+  }
+  /// 深复制值。数组与对象中的每一层都会被复制，得到的值与原值不共享任何部分。
+  /// 原值内部共享或循环引用的容器，在副本中同样共享或循环引用。
+  /// ```
+  /// let a = Var::parse("[[1]]").unwrap();
+  /// let b = a.deep_clone();
+  /// if let (Var::Array(a), Var::Array(b)) = (&a, &b) {
+  ///   assert!(!Rc::ptr_eq(a, b));
+  /// }
+  /// ```
+  pub fn deep_clone(&self) -> Var {
+    self.deep_clone_with(&mut HashMap::new())
+  }
+  /// `copied` 记录已经复制过的容器及其副本。
+  fn deep_clone_with(&self, copied: &mut HashMap<usize, Var>) -> Var {
+    if let Some(id) = self.container_id() {
+      if let Some(val) = copied.get(&id) {
+        return val.clone();
+      }
+    }
+    match self {
+      Var::Array(val) => {
+        let ret = Var::from(Vec::new());
+        copied.insert(self.container_id().unwrap_or_default(), ret.clone());
+        if let Var::Array(arr) = &ret {
+          for item in val.borrow().iter() {
+            let item = item.borrow().deep_clone_with(copied);
+            arr.borrow_mut().push(Rc::new(RefCell::new(item)));
+          }
         }
-        Var::Array(a)
+        ret
       }
-      Var::Object(v) => {
-        let mut a = BTreeMap::new();
-        for (key, value) in v.iter() {
-          a.insert(key.clone(), Rc::new(RefCell::new(value.borrow().clone())));
+      Var::Object(val) => {
+        let ret = Var::from(IndexMap::<Symbol, Rc<RefCell<Var>>>::new());
+        copied.insert(self.container_id().unwrap_or_default(), ret.clone());
+        if let Var::Object(obj) = &ret {
+          for (key, value) in val.borrow().iter() {
+            let value = value.borrow().deep_clone_with(copied);
+            obj
+              .borrow_mut()
+              .insert(key.clone(), Rc::new(RefCell::new(value)));
+          }
         }
-        Var::Object(a)
+        ret
       }
-      Var::Function(v) => Var::Function(v.clone()),
-      Var::Statement(v) => Var::Statement(v.clone()),
-      Var::Expression(v) => Var::Expression(v.clone()),
+      Var::Map(val) => {
+        let ret = Var::from(IndexMap::<VarKey, Rc<RefCell<Var>>>::new());
+        copied.insert(self.container_id().unwrap_or_default(), ret.clone());
+        if let Var::Map(map) = &ret {
+          for (key, value) in val.borrow().iter() {
+            let value = value.borrow().deep_clone_with(copied);
+            map
+              .borrow_mut()
+              .insert(key.clone(), Rc::new(RefCell::new(value)));
+          }
+        }
+        ret
+      }
+      _ => self.clone(),
     }
   }
 }
 // convert
 impl Var {
   pub fn convert(self, tp: ValueType) -> Result<Var, Error> {
+    if self.tp() == tp {
+      return Ok(self);
+    }
     match tp {
       ValueType::Null => Ok(Var::Null(TryInto::<()>::try_into(self)?)),
       ValueType::Boolean => Ok(Var::Boolean(TryInto::<bool>::try_into(self)?)),
       ValueType::Number => Ok(Var::Number(TryInto::<f64>::try_into(self)?)),
+      ValueType::Integer => Ok(Var::Integer(TryInto::<i64>::try_into(self)?)),
+      ValueType::BigInt => Ok(Var::BigInt(TryInto::<BigInt>::try_into(self)?)),
+      ValueType::Decimal => Ok(Var::Decimal(TryInto::<Decimal>::try_into(self)?)),
       ValueType::String => Ok(Var::String(TryInto::<String>::try_into(self)?)),
-      ValueType::Array => Ok(Var::Array(TryInto::<Vec<Rc<RefCell<Var>>>>::try_into(
+      ValueType::Bytes => Ok(Var::Bytes(TryInto::<Vec<u8>>::try_into(self)?)),
+      ValueType::DateTime => Ok(Var::DateTime(TryInto::<DateTime<FixedOffset>>::try_into(
         self,
       )?)),
-      ValueType::Object => Ok(Var::Object(
-        TryInto::<BTreeMap<String, Rc<RefCell<Var>>>>::try_into(self)?,
+      ValueType::Duration => Ok(Var::Duration(TryInto::<TimeDelta>::try_into(self)?)),
+      ValueType::Regex => Ok(Var::Regex(TryInto::<RegexValue>::try_into(self)?)),
+      ValueType::Array => Ok(Var::from(TryInto::<Vec<Rc<RefCell<Var>>>>::try_into(self)?)),
+      ValueType::Object => Ok(Var::from(
+        TryInto::<IndexMap<Symbol, Rc<RefCell<Var>>>>::try_into(self)?,
       )),
+      ValueType::Map => Ok(Var::from(
+        TryInto::<IndexMap<VarKey, Rc<RefCell<Var>>>>::try_into(self)?,
+      )),
+      ValueType::Set => Ok(Var::Set(TryInto::<IndexSet<VarKey>>::try_into(self)?)),
       _ => Err(Error::from("Conversion failed")),
     }
   }
@@ -554,6 +873,9 @@ impl TryFrom<Var> for bool {
     match val {
       Var::Boolean(val) => Ok(val),
       Var::Number(val) => Ok(val != 0.0),
+      Var::Integer(val) => Ok(val != 0),
+      Var::BigInt(val) => Ok(!val.is_zero()),
+      Var::Decimal(val) => Ok(!val.is_zero()),
       _ => Err(Error::from("Conversion failed")),
     }
   }
@@ -563,34 +885,250 @@ impl TryFrom<Var> for f64 {
   fn try_from(val: Var) -> Result<Self, Self::Error> {
     match val {
       Var::Number(val) => Ok(val),
+      Var::Integer(val) => Ok(val as f64),
+      Var::BigInt(val) => Ok(val.to_f64().unwrap_or(f64::NAN)),
+      Var::Decimal(val) => Ok(val.to_f64().unwrap_or(f64::NAN)),
       Var::Boolean(val) => Ok(if val { 1.0 } else { 0.0 }),
       _ => Err(Error::from("Conversion failed")),
     }
   }
 }
+impl TryFrom<Var> for i64 {
+  type Error = Error;
+  /// `Number` 只有在为整数且不超出 `i64` 范围时才能转换。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Integer(val) => Ok(val),
+      Var::Number(val) => {
+        if val.fract() == 0.0 && (-9223372036854775808.0..9223372036854775808.0).contains(&val) {
+          Ok(val as i64)
+        } else {
+          Err(Error::from("Conversion failed"))
+        }
+      }
+      Var::BigInt(val) => match val.to_i64() {
+        Some(val) => Ok(val),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Decimal(val) if val.fract().is_zero() => match val.to_i64() {
+        Some(val) => Ok(val),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Boolean(val) => Ok(if val { 1 } else { 0 }),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for BigInt {
+  type Error = Error;
+  /// `Number` 只有在为有限的整数时才能转换。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::BigInt(val) => Ok(val),
+      Var::Integer(val) => Ok(BigInt::from(val)),
+      Var::Number(val) if val.fract() == 0.0 => match BigInt::from_f64(val) {
+        Some(val) => Ok(val),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Decimal(val) if val.fract().is_zero() => match val.to_i128() {
+        Some(val) => Ok(BigInt::from(val)),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Boolean(val) => Ok(BigInt::from(if val { 1 } else { 0 })),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for Decimal {
+  type Error = Error;
+  /// `String` 将按十进制字面量精确解析，`Number` 则取其最短表示。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Decimal(val) => Ok(val),
+      Var::Integer(val) => Ok(Decimal::from(val)),
+      Var::BigInt(val) => match val.to_i128().and_then(Decimal::from_i128) {
+        Some(val) => Ok(val),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Number(val) => match Decimal::from_f64(val) {
+        Some(val) => Ok(val),
+        None => Err(Error::from("Conversion failed")),
+      },
+      Var::Boolean(val) => Ok(if val { Decimal::ONE } else { Decimal::ZERO }),
+      Var::String(val) => match Decimal::from_str_exact(val.trim()) {
+        Ok(val) => Ok(val),
+        Err(_) => Err(Error::from("Conversion failed")),
+      },
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
 impl TryFrom<Var> for String {
   type Error = Error;
   fn try_from(val: Var) -> Result<Self, Self::Error> {
     match val {
       Var::String(val) => Ok(val),
+      Var::Bytes(val) => match String::from_utf8(val) {
+        Ok(val) => Ok(val),
+        Err(_) => Err(Error::from("Conversion failed")),
+      },
+      Var::DateTime(val) => time::format(&val, None),
+      Var::Duration(val) => Ok(val.to_string()),
+      Var::Number(val) => Ok(number::to_string(val)),
       _ => Ok(val.to_string()),
     }
   }
 }
+impl TryFrom<Var> for DateTime<FixedOffset> {
+  type Error = Error;
+  /// `String` 按 RFC 3339 格式解析。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::DateTime(val) => Ok(val),
+      Var::String(val) => time::parse(val.as_str()),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for RegexValue {
+  type Error = Error;
+  /// `String` 作为不带标志的模式编译。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Regex(val) => Ok(val),
+      Var::String(val) => pattern::compile(val.as_str(), ""),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for TimeDelta {
+  type Error = Error;
+  /// `String` 按 ISO 8601 格式解析。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Duration(val) => Ok(val),
+      Var::String(val) => time::parse_duration(val.as_str()),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for Vec<u8> {
+  type Error = Error;
+  /// `String` 按 UTF-8 编码，`Array` 的每个元素必须为 `0..=255` 内的整数。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Bytes(val) => Ok(val),
+      Var::String(val) => Ok(val.into_bytes()),
+      Var::Array(val) => {
+        let mut ret: Vec<u8> = vec![];
+        for item in val.borrow().iter() {
+          match u8::try_from(TryInto::<i64>::try_into(item.borrow().clone())?) {
+            Ok(item) => ret.push(item),
+            Err(_) => return Err(Error::from("Conversion failed")),
+          }
+        }
+        Ok(ret)
+      }
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
 impl TryFrom<Var> for Vec<Rc<RefCell<Var>>> {
   type Error = Error;
+  /// 得到的元素与原数组共享。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Array(val) => Ok(val.borrow().clone()),
+      Var::Bytes(val) => Ok(
+        val
+          .iter()
+          .map(|item| Rc::new(RefCell::new(Var::Integer(*item as i64))))
+          .collect(),
+      ),
+      Var::Set(val) => Ok(
+        val
+          .iter()
+          .map(|item| Rc::new(RefCell::new(Var::from(item))))
+          .collect(),
+      ),
+      Var::Map(val) => Ok(
+        val
+          .borrow()
+          .iter()
+          .map(|(key, value)| {
+            Rc::new(RefCell::new(Var::from(vec![
+              Rc::new(RefCell::new(Var::from(key))),
+              Rc::new(RefCell::new(value.borrow().clone())),
+            ])))
+          })
+          .collect(),
+      ),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for IndexMap<Symbol, Rc<RefCell<Var>>> {
+  type Error = Error;
+  /// 得到的属性与原对象共享。
+  fn try_from(val: Var) -> Result<Self, Self::Error> {
+    match val {
+      Var::Object(val) => Ok(val.borrow().clone()),
+      _ => Err(Error::from("Conversion failed")),
+    }
+  }
+}
+impl TryFrom<Var> for IndexMap<VarKey, Rc<RefCell<Var>>> {
+  type Error = Error;
+  /// `Object` 以字符串为键转换，`Array` 的每个元素必须为 `[key, value]`。
+  /// 由 `Map` 得到的值与原 `Map` 共享。
   fn try_from(val: Var) -> Result<Self, Self::Error> {
     match val {
-      Var::Array(val) => Ok(val),
+      Var::Map(val) => Ok(val.borrow().clone()),
+      Var::Object(val) => Ok(
+        val
+          .borrow()
+          .iter()
+          .map(|(key, value)| {
+            (
+              VarKey::String(key.to_string()),
+              Rc::new(RefCell::new(value.borrow().clone())),
+            )
+          })
+          .collect(),
+      ),
+      Var::Array(val) => {
+        let mut ret = IndexMap::new();
+        for item in val.borrow().iter() {
+          if let Var::Array(pair) = &*item.borrow() {
+            let pair = pair.borrow();
+            if pair.len() == 2 {
+              ret.insert(
+                VarKey::try_from(&*pair[0].borrow())?,
+                Rc::new(RefCell::new(pair[1].borrow().clone())),
+              );
+              continue;
+            }
+          }
+          return Err(Error::from("Conversion failed"));
+        }
+        Ok(ret)
+      }
       _ => Err(Error::from("Conversion failed")),
     }
   }
 }
-impl TryFrom<Var> for BTreeMap<String, Rc<RefCell<Var>>> {
+impl TryFrom<Var> for IndexSet<VarKey> {
   type Error = Error;
   fn try_from(val: Var) -> Result<Self, Self::Error> {
     match val {
-      Var::Object(val) => Ok(val),
+      Var::Set(val) => Ok(val),
+      Var::Array(val) => {
+        let mut ret = IndexSet::new();
+        for item in val.borrow().iter() {
+          ret.insert(VarKey::try_from(&*item.borrow())?);
+        }
+        Ok(ret)
+      }
       _ => Err(Error::from("Conversion failed")),
     }
   }
@@ -598,6 +1136,40 @@ impl TryFrom<Var> for BTreeMap<String, Rc<RefCell<Var>>> {
 // opcall
 impl Var {
   pub fn opcall_single(self, op: char) -> Result<Var, Error> {
+    if let Var::Integer(val) = self {
+      return match op {
+        '~' => Ok(Var::Integer(!val)),
+        '-' => Var::checked(val.checked_neg()),
+        '+' => Ok(Var::Integer(val)),
+        '!' => Ok(Var::Boolean(val == 0)),
+        _ => Err(Error::from("Unknown operand")),
+      };
+    }
+    if let Var::BigInt(val) = self {
+      return match op {
+        '~' => Ok(Var::BigInt(!val)),
+        '-' => Ok(Var::BigInt(-val)),
+        '+' => Ok(Var::BigInt(val)),
+        '!' => Ok(Var::Boolean(val.is_zero())),
+        _ => Err(Error::from("Unknown operand")),
+      };
+    }
+    if let Var::Duration(val) = self {
+      return match op {
+        '-' => Ok(Var::Duration(-val)),
+        '+' => Ok(Var::Duration(val)),
+        '!' => Ok(Var::Boolean(val.is_zero())),
+        _ => Err(Error::from("Unknown operand")),
+      };
+    }
+    if let Var::Decimal(val) = self {
+      return match op {
+        '-' => Ok(Var::Decimal(-val)),
+        '+' => Ok(Var::Decimal(val)),
+        '!' => Ok(Var::Boolean(val.is_zero())),
+        _ => Err(Error::from("Unknown operand")),
+      };
+    }
     match op {
       '~' => Ok(Var::Number(f64::from(
         !((TryInto::<f64>::try_into(self)?) as i32),
@@ -608,7 +1180,60 @@ impl Var {
       _ => Err(Error::from("Unknown operand")),
     }
   }
+  /// 比较 `BigInt` 与 `Number`。`Number` 为 `NaN` 时无法比较。
+  fn cmp_bigint(left: &BigInt, right: f64) -> Option<Ordering> {
+    if right.is_nan() {
+      None
+    } else if right.is_infinite() {
+      Some(if right > 0.0 {
+        Ordering::Less
+      } else {
+        Ordering::Greater
+      })
+    } else {
+      let floor = BigInt::from_f64(right.floor())?;
+      match left.cmp(&floor) {
+        Ordering::Equal if right.fract() != 0.0 => Some(Ordering::Less),
+        ord => Some(ord),
+      }
+    }
+  }
+  /// 比较数组或对象时，假定二者相等并记录下来（互模拟），
+  /// 以便比较循环引用的结构时能够终止。若此前已有相同的假定则返回 `false`。
+  /// 假定在最外层的 `opcmp` 结束时清除。
+  fn assume_equal(left: &Var, right: &Var) -> bool {
+    match (left.container_id(), right.container_id()) {
+      (Some(left), Some(right)) => {
+        EQUAL_ASSUMED.with(|assumed| assumed.borrow_mut().1.insert((left, right)))
+      }
+      _ => true,
+    }
+  }
   fn opcmp(&self, op: &str, val: &Var) -> Result<bool, Error> {
+    EQUAL_ASSUMED.with(|assumed| assumed.borrow_mut().0 += 1);
+    let ret = self.opcmp_inner(op, val);
+    EQUAL_ASSUMED.with(|assumed| {
+      let mut assumed = assumed.borrow_mut();
+      assumed.0 -= 1;
+      if assumed.0 == 0 {
+        assumed.1.clear();
+      }
+    });
+    ret
+  }
+  fn opcmp_inner(&self, op: &str, val: &Var) -> Result<bool, Error> {
+    if let (Var::Number(_), Var::BigInt(_)) = (self, val) {
+      return val.opcmp(
+        match op {
+          ">" => "<",
+          "<" => ">",
+          ">=" => "<=",
+          "<=" => ">=",
+          _ => op,
+        },
+        self,
+      );
+    }
     match op {
       "===" | "==" => match self {
         Var::Null(_) => {
@@ -632,6 +1257,27 @@ impl Var {
             Ok(false)
           }
         }
+        Var::Integer(left) => {
+          if let Var::Integer(right) = val {
+            Ok(left == right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Decimal(left) => {
+          if let Var::Decimal(right) = val {
+            Ok(left == right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::BigInt(left) => match val {
+          Var::BigInt(right) => Ok(left == right),
+          Var::Number(right) if op == "==" => {
+            Ok(Var::cmp_bigint(left, *right) == Some(Ordering::Equal))
+          }
+          _ => Ok(false),
+        },
         Var::String(left) => {
           if let Var::String(right) = val {
             Ok(left == right)
@@ -639,16 +1285,60 @@ impl Var {
             Ok(false)
           }
         }
+        Var::Bytes(left) => {
+          if let Var::Bytes(right) = val {
+            Ok(left == right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::DateTime(left) => {
+          if let Var::DateTime(right) = val {
+            Ok(left == right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Duration(left) => {
+          if let Var::Duration(right) = val {
+            Ok(left == right)
+          } else {
+            Ok(false)
+          }
+        }
         Var::Array(left) => {
           if let Var::Array(right) = val {
+            if Rc::ptr_eq(left, right) || !Var::assume_equal(self, val) {
+              return Ok(true);
+            }
+            let (left, right) = (left.borrow(), right.borrow());
             if left.len() == right.len() {
               Ok(left.iter().enumerate().all(|(index, item)| {
-                if let Ok(val) = item.borrow().clone().opcall(op, &*right[index].borrow()) {
-                  if let Var::Boolean(val) = val {
-                    val
-                  } else {
-                    false
-                  }
+                matches!(
+                  item.borrow().clone().opcall(op, &right[index].borrow()),
+                  Ok(Var::Boolean(true))
+                )
+              }))
+            } else {
+              Ok(false)
+            }
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Object(left) => {
+          if let Var::Object(right) = val {
+            if Rc::ptr_eq(left, right) || !Var::assume_equal(self, val) {
+              return Ok(true);
+            }
+            let (left, right) = (left.borrow(), right.borrow());
+            if left.len() == right.len() {
+              Ok(left.iter().all(|(key, value)| {
+                if let Some(r_val) = right.get(key) {
+                  matches!(
+                    value.borrow().clone().opcall(op, &r_val.borrow()),
+                    Ok(Var::Boolean(true))
+                  )
                 } else {
                   false
                 }
@@ -660,20 +1350,19 @@ impl Var {
             Ok(false)
           }
         }
-        Var::Object(left) => {
-          if let Var::Object(right) = val {
+        Var::Map(left) => {
+          if let Var::Map(right) = val {
+            if Rc::ptr_eq(left, right) || !Var::assume_equal(self, val) {
+              return Ok(true);
+            }
+            let (left, right) = (left.borrow(), right.borrow());
             if left.len() == right.len() {
               Ok(left.iter().all(|(key, value)| {
                 if let Some(r_val) = right.get(key) {
-                  if let Ok(val) = value.borrow().clone().opcall(op, &*r_val.borrow()) {
-                    if let Var::Boolean(val) = val {
-                      val
-                    } else {
-                      false
-                    }
-                  } else {
-                    false
-                  }
+                  matches!(
+                    value.borrow().clone().opcall(op, &r_val.borrow()),
+                    Ok(Var::Boolean(true))
+                  )
                 } else {
                   false
                 }
@@ -685,6 +1374,13 @@ impl Var {
             Ok(false)
           }
         }
+        Var::Set(left) => {
+          if let Var::Set(right) = val {
+            Ok(left.len() == right.len() && left.iter().all(|item| right.contains(item)))
+          } else {
+            Ok(false)
+          }
+        }
         Var::Function(left) => {
           if let Var::Function(right) = val {
             Ok(left == right)
@@ -692,36 +1388,131 @@ impl Var {
             Ok(false)
           }
         }
-        _ => Ok(false),
-      },
-      "!=" | "!==" => Ok(!(self.opcmp(op, val)?)),
-      ">" => match self {
-        Var::Number(left) => {
-          if let Var::Number(right) = val {
-            Ok(*left > *right)
+        Var::Builtin(left) => {
+          if let Var::Builtin(right) = val {
+            Ok(left == right)
           } else {
             Ok(false)
           }
         }
-        Var::String(left) => {
-          if let Var::String(right) = val {
-            Ok(left > right)
+        Var::Regex(left) => {
+          if let Var::Regex(right) = val {
+            Ok(left == right)
           } else {
             Ok(false)
           }
         }
         _ => Ok(false),
       },
-      "<" => match self {
+      "!=" => Ok(!(self.opcmp("==", val)?)),
+      "!==" => Ok(!(self.opcmp("===", val)?)),
+      ">" => match self {
         Var::Number(left) => {
           if let Var::Number(right) = val {
-            Ok(*left < *right)
+            Ok(*left > *right)
           } else {
             Ok(false)
           }
         }
+        Var::Integer(left) => {
+          if let Var::Integer(right) = val {
+            Ok(*left > *right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Decimal(left) => {
+          if let Var::Decimal(right) = val {
+            Ok(left > right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::BigInt(left) => match val {
+          Var::BigInt(right) => Ok(left > right),
+          Var::Number(right) => Ok(Var::cmp_bigint(left, *right) == Some(Ordering::Greater)),
+          _ => Ok(false),
+        },
         Var::String(left) => {
           if let Var::String(right) = val {
+            Ok(left > right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Bytes(left) => {
+          if let Var::Bytes(right) = val {
+            Ok(left > right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::DateTime(left) => {
+          if let Var::DateTime(right) = val {
+            Ok(left > right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Duration(left) => {
+          if let Var::Duration(right) = val {
+            Ok(left > right)
+          } else {
+            Ok(false)
+          }
+        }
+        _ => Ok(false),
+      },
+      "<" => match self {
+        Var::Number(left) => {
+          if let Var::Number(right) = val {
+            Ok(*left < *right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Integer(left) => {
+          if let Var::Integer(right) = val {
+            Ok(*left < *right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Decimal(left) => {
+          if let Var::Decimal(right) = val {
+            Ok(left < right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::BigInt(left) => match val {
+          Var::BigInt(right) => Ok(left < right),
+          Var::Number(right) => Ok(Var::cmp_bigint(left, *right) == Some(Ordering::Less)),
+          _ => Ok(false),
+        },
+        Var::String(left) => {
+          if let Var::String(right) = val {
+            Ok(left < right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Bytes(left) => {
+          if let Var::Bytes(right) = val {
+            Ok(left < right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::DateTime(left) => {
+          if let Var::DateTime(right) = val {
+            Ok(left < right)
+          } else {
+            Ok(false)
+          }
+        }
+        Var::Duration(left) => {
+          if let Var::Duration(right) = val {
             Ok(left < right)
           } else {
             Ok(false)
@@ -734,16 +1525,120 @@ impl Var {
       _ => Err(Error::from("Unknown operand")),
     }
   }
+  fn rounded(val: Option<Decimal>) -> Result<Var, Error> {
+    match val {
+      Some(val) => Ok(Var::Decimal(DecimalContext::get().round(val))),
+      None => Err(Error::from("Decimal overflow")),
+    }
+  }
+  fn checked(val: Option<i64>) -> Result<Var, Error> {
+    match val {
+      Some(val) => Ok(Var::Integer(val)),
+      None => Err(Error::from("Integer overflow")),
+    }
+  }
+  /// 进行 `DateTime` 或 `Duration` 参与的二元运算。
+  /// 时间加减时长得到时间，两个时间相减得到时长；时长之间可以加减，
+  /// 也可以乘除 `Integer` 或 `Number`，两个时长相除得到 `Number`。
+  fn opcall_time(self, op: &str, val: &Var) -> Result<Var, Error> {
+    let ret = match (op, &self, val) {
+      ("==" | "!=" | ">=" | "<=" | ">" | "<" | "===" | "!==", _, _) => {
+        return Ok(Var::Boolean(self.opcmp(op, val)?))
+      }
+      ("+", Var::DateTime(left), Var::Duration(right))
+      | ("+", Var::Duration(right), Var::DateTime(left)) => {
+        left.checked_add_signed(*right).map(Var::DateTime)
+      }
+      ("-", Var::DateTime(left), Var::Duration(right)) => {
+        left.checked_sub_signed(*right).map(Var::DateTime)
+      }
+      ("-", Var::DateTime(left), Var::DateTime(right)) => {
+        Some(Var::Duration(left.signed_duration_since(*right)))
+      }
+      ("+", Var::Duration(left), Var::Duration(right)) => {
+        left.checked_add(right).map(Var::Duration)
+      }
+      ("-", Var::Duration(left), Var::Duration(right)) => {
+        left.checked_sub(right).map(Var::Duration)
+      }
+      ("*", Var::Duration(left), right) => return Ok(Var::Duration(time::mul(left, right)?)),
+      ("*", left, Var::Duration(right)) => return Ok(Var::Duration(time::mul(right, left)?)),
+      ("/", Var::Duration(left), right) => return time::div(left, right),
+      _ => return Err(Error::from("Calculation failed")),
+    };
+    match ret {
+      Some(val) => Ok(val),
+      None => Err(Error::from("Date out of range")),
+    }
+  }
+  /// 求出二元运算两侧操作数的公共类型。
+  /// `Integer` 与 `Number` 混合时，位运算按 `Integer` 计算（`Number` 必须为整数），
+  /// 其余运算均提升为 `Number`。`Integer` 与 `BigInt` 混合时提升为 `BigInt`，
+  /// 二者与 `Decimal` 混合时提升为 `Decimal`。
+  /// 其他情况下以右操作数的类型为准。
+  fn common_type(&self, op: &str, val: &Var) -> ValueType {
+    match (self, val) {
+      (Var::Integer(_), Var::Number(_)) | (Var::Number(_), Var::Integer(_)) => match op {
+        "&" | "|" | "^" | "<<" | ">>" | ">>>" => ValueType::Integer,
+        _ => ValueType::Number,
+      },
+      (Var::Integer(_), Var::BigInt(_)) | (Var::BigInt(_), Var::Integer(_)) => ValueType::BigInt,
+      (Var::Decimal(_), Var::Integer(_) | Var::BigInt(_))
+      | (Var::Integer(_) | Var::BigInt(_), Var::Decimal(_)) => ValueType::Decimal,
+      _ => val.tp(),
+    }
+  }
+  /// 进行二元运算。
+  /// `Integer` 之间的 `+`、`-`、`*` 会检查溢出，`/` 总是得到 `Number`。
+  /// `Integer`、`BigInt` 与 `Decimal` 的 `/` 与 `%` 除以零时返回错误。
+  /// `===` 与 `!==` 不进行类型提升，类型不同的值总是不相等。
+  /// `BigInt`、`Decimal` 与 `Number` 之间只能进行比较，其余运算将返回错误。
+  /// `Decimal` 的运算结果按照 `DecimalContext` 舍入。
   pub fn opcall(self, op: &str, val: &Var) -> Result<Var, Error> {
+    if let (Var::DateTime(_) | Var::Duration(_), _) | (_, Var::DateTime(_) | Var::Duration(_)) =
+      (&self, val)
+    {
+      return self.opcall_time(op, val);
+    }
+    if let (Var::BigInt(_), Var::Number(_)) | (Var::Number(_), Var::BigInt(_)) = (&self, val) {
+      return match op {
+        "==" | "!=" | ">=" | "<=" | ">" | "<" | "===" | "!==" => {
+          Ok(Var::Boolean(self.opcmp(op, val)?))
+        }
+        _ => Err(Error::from("Cannot mix BigInt and Number")),
+      };
+    }
+    if let (Var::Decimal(_), Var::Number(_)) | (Var::Number(_), Var::Decimal(_)) = (&self, val) {
+      return match op {
+        "==" | "!=" | ">=" | "<=" | ">" | "<" => Ok(Var::Boolean(
+          self
+            .convert(ValueType::Number)?
+            .opcmp(op, &val.clone().convert(ValueType::Number)?)?,
+        )),
+        "===" => Ok(Var::Boolean(false)),
+        "!==" => Ok(Var::Boolean(true)),
+        _ => Err(Error::from("Cannot mix Decimal and Number")),
+      };
+    }
+    if let "===" | "!==" = op {
+      return Ok(Var::Boolean(self.opcmp(op, val)?));
+    }
+    let promoted: Var;
+    let tp = self.common_type(op, val);
+    let val = if val.tp() != tp {
+      promoted = val.clone().convert(tp)?;
+      &promoted
+    } else {
+      val
+    };
     match op {
       "==" | "!=" | ">=" | "<=" | ">" | "<" => {
         if let Ok(conv) = self.convert(val.tp()) {
-          Ok(Var::Boolean(conv.opcmp(op, &val)?))
+          Ok(Var::Boolean(conv.opcmp(op, val)?))
         } else {
           Ok(Var::Boolean(false))
         }
       }
-      "===" | "!==" => Ok(Var::Boolean(self.opcmp(op, &val)?)),
       _ => {
         let conv = self.convert(val.tp())?;
         match op {
@@ -755,6 +1650,13 @@ impl Var {
                 Err(Error::from("Calculation failed"))
               }
             }
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(left.checked_add(*right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
             Var::String(left) => {
               if let Var::String(right) = val {
                 Ok(Var::String(left + right.as_str()))
@@ -762,41 +1664,108 @@ impl Var {
                 Err(Error::from("Calculation failed"))
               }
             }
+            Var::Bytes(mut left) => {
+              if let Var::Bytes(right) = val {
+                left.extend_from_slice(right);
+                Ok(Var::Bytes(left))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
             Var::Array(left) => {
               if let Var::Array(right) = val {
-                let mut s = left.clone();
-                s.append(&mut right.clone());
-                Ok(Var::Array(s))
+                Ok(Var::from(
+                  left
+                    .borrow()
+                    .iter()
+                    .chain(right.borrow().iter())
+                    .map(|item| Rc::new(RefCell::new(item.borrow().clone())))
+                    .collect::<Vec<Rc<RefCell<Var>>>>(),
+                ))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left + right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::Decimal(left) => {
+              if let Var::Decimal(right) = val {
+                Var::rounded(left.checked_add(*right))
               } else {
                 Err(Error::from("Calculation failed"))
               }
             }
             _ => Err(Error::from("Calculation failed")),
           },
-          "-" => {
-            if let Var::Number(left) = conv {
+          "-" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(left - right))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "*" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(left.checked_sub(*right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left - right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::Decimal(left) => {
+              if let Var::Decimal(right) = val {
+                Var::rounded(left.checked_sub(*right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "*" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(left * right))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "/" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(left.checked_mul(*right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left * right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::Decimal(left) => {
+              if let Var::Decimal(right) = val {
+                Var::rounded(left.checked_mul(*right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "/" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 if *right != 0.0 {
                   Ok(Var::Number(left / right))
@@ -806,12 +1775,44 @@ impl Var {
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "%" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                if *right != 0 {
+                  Ok(Var::Number(left as f64 / *right as f64))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                if !right.is_zero() {
+                  Ok(Var::BigInt(left / right))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::Decimal(left) => {
+              if let Var::Decimal(right) = val {
+                if !right.is_zero() {
+                  Var::rounded(left.checked_div(*right))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "%" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 if *right != 0.0 {
                   Ok(Var::Number(left % right))
@@ -821,87 +1822,222 @@ impl Var {
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "&" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                if *right != 0 {
+                  Ok(Var::Integer(left.wrapping_rem(*right)))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                if !right.is_zero() {
+                  Ok(Var::BigInt(left % right))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::Decimal(left) => {
+              if let Var::Decimal(right) = val {
+                if !right.is_zero() {
+                  Var::rounded(left.checked_rem(*right))
+                } else {
+                  Err(Error::from("Division by zero"))
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "&" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as i32) & (*right as i32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "|" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Ok(Var::Integer(left & right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left & right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "|" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as i32) | (*right as i32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "^" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Ok(Var::Integer(left | right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left | right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "^" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as i32) ^ (*right as i32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "<<" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Ok(Var::Integer(left ^ right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                Ok(Var::BigInt(left ^ right))
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "<<" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as i32) << (*right as i32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          ">>" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(
+                  u32::try_from(*right)
+                    .ok()
+                    .and_then(|right| left.checked_shl(right)),
+                )
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                match right.to_usize() {
+                  Some(right) => Ok(Var::BigInt(left << right)),
+                  None => Err(Error::from("Shift out of range")),
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          ">>" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as i32) >> (*right as i32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          "<<<" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(
+                  u32::try_from(*right)
+                    .ok()
+                    .and_then(|right| left.checked_shr(right)),
+                )
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(left) => {
+              if let Var::BigInt(right) = val {
+                match right.to_usize() {
+                  Some(right) => Ok(Var::BigInt(left >> right)),
+                  None => Err(Error::from("Shift out of range")),
+                }
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            _ => Err(Error::from("Calculation failed")),
+          },
+          "<<<" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as u32) << (*right as u32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
-          ">>>" => {
-            if let Var::Number(left) = conv {
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(
+                  u32::try_from(*right)
+                    .ok()
+                    .and_then(|right| (left as u64).checked_shl(right))
+                    .map(|val| val as i64),
+                )
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(_) => Err(Error::from("BigInt has no unsigned shift")),
+            _ => Err(Error::from("Calculation failed")),
+          },
+          ">>>" => match conv {
+            Var::Number(left) => {
               if let Var::Number(right) = val {
                 Ok(Var::Number(((left as u32) >> (*right as u32)) as f64))
               } else {
                 Err(Error::from("Calculation failed"))
               }
-            } else {
-              Err(Error::from("Calculation failed"))
             }
-          }
+            Var::Integer(left) => {
+              if let Var::Integer(right) = val {
+                Var::checked(
+                  u32::try_from(*right)
+                    .ok()
+                    .and_then(|right| (left as u64).checked_shr(right))
+                    .map(|val| val as i64),
+                )
+              } else {
+                Err(Error::from("Calculation failed"))
+              }
+            }
+            Var::BigInt(_) => Err(Error::from("BigInt has no unsigned shift")),
+            _ => Err(Error::from("Calculation failed")),
+          },
           _ => Err(Error::from("Unknown operand")),
         }
       }
@@ -909,23 +2045,120 @@ impl Var {
   }
 }
 // from
+impl Default for Var {
+  fn default() -> Self {
+    Self::new()
+  }
+}
 impl Var {
   pub fn new() -> Self {
     Var::Null(())
   }
+  /// 解析 `BigInt` 字面量，如 `123n`、`0xffn`。
+  fn parse_bigint(str: &str) -> Option<BigInt> {
+    let body = str.strip_suffix('n')?;
+    let (digits, radix) = if let Some(val) = body.strip_prefix("0x") {
+      (val, 16)
+    } else if let Some(val) = body.strip_prefix("0o") {
+      (val, 8)
+    } else if let Some(val) = body.strip_prefix("0b") {
+      (val, 2)
+    } else {
+      (body, 10)
+    };
+    if digits.is_empty() || !digits.chars().all(|item| item.is_digit(radix)) {
+      return None;
+    }
+    BigInt::parse_bytes(digits.as_bytes(), radix)
+  }
+  /// 解析 `Decimal` 字面量，如 `0.1m`、`19.99m`。
+  fn parse_decimal(str: &str) -> Option<Decimal> {
+    let body = str.strip_suffix('m')?;
+    let mut parts = body.splitn(2, '.');
+    let int = parts.next()?;
+    let frac = parts.next().unwrap_or("0");
+    if int.is_empty()
+      || frac.is_empty()
+      || !int.chars().all(|item| item.is_ascii_digit())
+      || !frac.chars().all(|item| item.is_ascii_digit())
+    {
+      return None;
+    }
+    Decimal::from_str_exact(body).ok()
+  }
+  /// 解析十六进制的 `Bytes` 字面量，如 `x"deadbeef"`。
+  fn parse_bytes(str: &str) -> Option<Vec<u8>> {
+    let body = str.strip_prefix("x\"")?.strip_suffix('"')?;
+    bytes::decode(body, bytes::Encoding::Hex).ok()
+  }
+  /// 解析 RFC 3339 格式的 `DateTime` 字面量，如 `d"2024-01-01T00:00:00Z"`。
+  fn parse_datetime(str: &str) -> Option<DateTime<FixedOffset>> {
+    let body = str.strip_prefix("d\"")?.strip_suffix('"')?;
+    time::parse(body).ok()
+  }
+  /// 解析 ISO 8601 格式的 `Duration` 字面量，如 `t"PT1.5S"`。
+  fn parse_duration(str: &str) -> Option<TimeDelta> {
+    let body = str.strip_prefix("t\"")?.strip_suffix('"')?;
+    time::parse_duration(body).ok()
+  }
+  /// 解析正则表达式字面量，如 `r"\d+"g`，返回模式与标志。
+  /// 模式中的 `\"` 表示 `"`，其余的反斜杠原样保留。
+  fn parse_regex(str: &str) -> Option<(String, String)> {
+    let body = str.strip_prefix("r\"")?;
+    let (body, flags) = body.rsplit_once('"')?;
+    if !flags.chars().all(|item| item.is_ascii_alphabetic()) {
+      return None;
+    }
+    let mut source = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(item) = chars.next() {
+      if item == '\\' && chars.peek() == Some(&'"') {
+        continue;
+      }
+      source.push(item);
+      if item == '\\' {
+        source.extend(chars.next());
+      }
+    }
+    Some((source, flags.to_string()))
+  }
   pub fn parse(str: &str) -> Result<Self, Error> {
     let raw = clearnull(str);
     let p = raw.as_str();
-    if p == "" {
+    if p.is_empty() {
       return Ok(Var::new());
     } else if !ExprValue::isexp(p) {
+      if let Some(val) = Var::parse_bigint(p) {
+        return Ok(Var::BigInt(val));
+      }
+      if let Some(val) = Var::parse_decimal(p) {
+        return Ok(Var::Decimal(val));
+      }
+      if let Some(val) = Var::parse_bytes(p) {
+        return Ok(Var::Bytes(val));
+      }
+      if let Some(val) = Var::parse_datetime(p) {
+        return Ok(Var::DateTime(val));
+      }
+      if let Some(val) = Var::parse_duration(p) {
+        return Ok(Var::Duration(val));
+      }
+      if let Some((source, flags)) = Var::parse_regex(p) {
+        return Ok(Var::Regex(pattern::compile(&source, &flags)?));
+      }
       {
         let res = {
-          let mut opt: Option<f64> = None;
+          let mut opt: Option<Var> = None;
           let mut flag: bool = true;
+          let mut last = ' ';
           for item in p.chars() {
-            if !((item >= '0' && item <= '9')
-              || (item >= 'a' && item <= 'f')
+            if (item == '+' || item == '-') && last == 'e' && !p.starts_with("0x") {
+              last = item;
+              continue;
+            }
+            last = item;
+            if !(item.is_ascii_digit()
+              || ('a'..='f').contains(&item)
               || item == 'o'
               || item == 'b'
               || item == 'x'
@@ -937,15 +2170,17 @@ impl Var {
             }
           }
           if flag {
-            if p.contains('.') || p.contains('e') {
+            if !p.starts_with("0x") && (p.contains('.') || p.contains('e')) {
               opt = if let Ok(val) = parse_int::parse::<f64>(p) {
-                Some(val)
+                Some(Var::Number(val))
               } else {
                 None
               };
             } else {
-              opt = if let Ok(val) = parse_int::parse::<i32>(p) {
-                Some(val as f64)
+              opt = if let Ok(val) = parse_int::parse::<i64>(p) {
+                Some(Var::Integer(val))
+              } else if let Ok(val) = parse_int::parse::<f64>(p) {
+                Some(Var::Number(val))
               } else {
                 None
               };
@@ -954,7 +2189,7 @@ impl Var {
           opt
         };
         if let Some(val) = res {
-          return Ok(Var::Number(val));
+          return Ok(val);
         }
       }
       if p == "null" {
@@ -969,7 +2204,6 @@ impl Var {
           if skip > 0 {
             skip -= 1;
           } else if item == '\n' || item == '\r' {
-            ()
           } else if item == '\\' {
             if let Some(val) = tmp.chars().nth(index + 1) {
               match val {
@@ -1013,9 +2247,9 @@ impl Var {
         for item in tmp.iter() {
           ret.push(Rc::new(RefCell::new(Var::parse(item.as_str())?)));
         }
-        return Ok(Var::Array(ret));
+        return Ok(Var::from(ret));
       } else if covered_with(p, '{', '}') {
-        let mut ret: BTreeMap<String, Rc<RefCell<Var>>> = BTreeMap::new();
+        let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
         let tmp = split_by(utf8_slice::slice(p, 1, utf8_slice::len(p) - 1), ',');
         for item in tmp.iter() {
           let pair = split_by(item.as_str(), ':');
@@ -1027,7 +2261,7 @@ impl Var {
               if let Var::String(str) = val {
                 match Var::parse(pair[1].as_str()) {
                   Ok(val) => {
-                    ret.insert(str, Rc::new(RefCell::new(val)));
+                    ret.insert(Symbol::from(str), Rc::new(RefCell::new(val)));
                   }
                   Err(_) => {
                     return Ok(Var::Statement(StmtValue::parse(p)));
@@ -1042,7 +2276,7 @@ impl Var {
             }
           }
         }
-        return Ok(Var::Object(ret));
+        return Ok(Var::from(ret));
       }
     }
     let mut exp = p;
@@ -1052,8 +2286,27 @@ impl Var {
     Ok(Var::Expression(ExprValue::parse(exp)?))
   }
 }
-impl ToString for Var {
-  fn to_string(&self) -> String {
+impl fmt::Display for Var {
+  /// 循环引用的数组或对象输出为 `[Circular]`。
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.to_string_with(&mut vec![]))
+  }
+}
+impl Var {
+  /// `path` 为正在输出的数组与对象，用于发现循环引用。
+  fn to_string_with(&self, path: &mut Vec<usize>) -> String {
+    if let Some(id) = self.container_id() {
+      if path.contains(&id) {
+        return String::from("[Circular]");
+      }
+      path.push(id);
+      let ret = self.to_string_inner(path);
+      path.pop();
+      return ret;
+    }
+    self.to_string_inner(path)
+  }
+  fn to_string_inner(&self, path: &mut Vec<usize>) -> String {
     match self {
       Var::Null(_) => String::from("null"),
       Var::Boolean(val) => {
@@ -1063,7 +2316,21 @@ impl ToString for Var {
           String::from("false")
         }
       }
-      Var::Number(val) => val.to_string(),
+      Var::Number(val) => {
+        let ret = number::to_string(*val);
+        if ret
+          .trim_start_matches('-')
+          .bytes()
+          .all(|item| item.is_ascii_digit())
+        {
+          ret + ".0"
+        } else {
+          ret
+        }
+      }
+      Var::Integer(val) => val.to_string(),
+      Var::BigInt(val) => format!("{}n", val),
+      Var::Decimal(val) => format!("{}m", val),
       Var::String(val) => {
         let mut tmp = String::from("\"");
         for item in val.chars() {
@@ -1078,10 +2345,18 @@ impl ToString for Var {
         }
         tmp + "\""
       }
+      Var::Bytes(val) => format!(
+        "x\"{}\"",
+        bytes::encode(val, bytes::Encoding::Hex).unwrap_or_default()
+      ),
+      Var::DateTime(val) => format!("d\"{}\"", time::format(val, None).unwrap_or_default()),
+      Var::Duration(val) => format!("t\"{}\"", val),
+      Var::Regex(val) => format!("r\"{}\"{}", val.source.replace('"', "\\\""), val.flags),
       Var::Array(val) => {
+        let val = val.borrow();
         let mut tmp = String::from("[");
         for (index, item) in val.iter().enumerate() {
-          tmp += item.borrow().to_string().as_str();
+          tmp += item.borrow().to_string_with(path).as_str();
           if index + 1 < val.len() {
             tmp.push(',');
           }
@@ -1089,12 +2364,13 @@ impl ToString for Var {
         tmp + "]"
       }
       Var::Object(val) => {
+        let val = val.borrow();
         let mut tmp = String::from("{");
         for (index, (key, value)) in val.iter().enumerate() {
           tmp += format!(
             "{}:{}",
-            Var::String(key.clone()).to_string(),
-            value.borrow().to_string()
+            Var::String(key.to_string()),
+            value.borrow().to_string_with(path)
           )
           .as_str();
           if index + 1 < val.len() {
@@ -1103,8 +2379,414 @@ impl ToString for Var {
         }
         tmp + "}"
       }
+      Var::Map(val) => {
+        let val = val.borrow();
+        let mut tmp = String::from("Map([");
+        for (index, (key, value)) in val.iter().enumerate() {
+          tmp += format!(
+            "[{},{}]",
+            Var::from(key),
+            value.borrow().to_string_with(path)
+          )
+          .as_str();
+          if index + 1 < val.len() {
+            tmp.push(',');
+          }
+        }
+        tmp + "])"
+      }
+      Var::Set(val) => {
+        let mut tmp = String::from("Set([");
+        for (index, item) in val.iter().enumerate() {
+          tmp += Var::from(item).to_string().as_str();
+          if index + 1 < val.len() {
+            tmp.push(',');
+          }
+        }
+        tmp + "])"
+      }
       Var::Function(val) => val.to_string(),
+      Var::Builtin(val) => format!("[builtin {}]", val),
       _ => String::from("<error-type>"),
     }
   }
 }
+// serde
+#[cfg(feature = "serde")]
+impl serde::Serialize for Var {
+  /// 将值序列化为任意 serde 格式。
+  /// `Decimal` 以字符串形式序列化，以免损失精度。
+  /// `Function`、`Statement` 与 `Expression` 不是数据，循环引用的数组或对象无法序列化，
+  /// 它们都将返回错误。
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let path = RefCell::new(vec![]);
+    Serialized {
+      val: self,
+      path: &path,
+    }
+    .serialize(serializer)
+  }
+}
+/// 正在序列化的值。`path` 为正在序列化的数组与对象，用于发现循环引用。
+#[cfg(feature = "serde")]
+struct Serialized<'a> {
+  val: &'a Var,
+  path: &'a RefCell<Vec<usize>>,
+}
+#[cfg(feature = "serde")]
+impl Serialized<'_> {
+  fn with<'b>(&'b self, val: &'b Var) -> Serialized<'b> {
+    Serialized {
+      val,
+      path: self.path,
+    }
+  }
+  fn serialize_inner<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    use serde::ser::{Error, SerializeMap, SerializeSeq};
+    match self.val {
+      Var::Null(_) => serializer.serialize_unit(),
+      Var::Boolean(val) => serializer.serialize_bool(*val),
+      Var::Number(val) => serializer.serialize_f64(*val),
+      Var::Integer(val) => serializer.serialize_i64(*val),
+      Var::BigInt(val) => match val.to_i128() {
+        Some(val) => serializer.serialize_i128(val),
+        None => Err(S::Error::custom("BigInt is out of range")),
+      },
+      Var::Decimal(val) => serializer.serialize_str(val.to_string().as_str()),
+      Var::String(val) => serializer.serialize_str(val.as_str()),
+      Var::Bytes(val) => serializer.serialize_bytes(val.as_slice()),
+      Var::DateTime(val) => {
+        serializer.serialize_str(time::format(val, None).unwrap_or_default().as_str())
+      }
+      Var::Duration(val) => serializer.serialize_str(val.to_string().as_str()),
+      Var::Regex(_) => serializer.serialize_str(self.val.to_string().as_str()),
+      Var::Array(val) => {
+        let val = val.borrow();
+        let mut seq = serializer.serialize_seq(Some(val.len()))?;
+        for item in val.iter() {
+          seq.serialize_element(&self.with(&item.borrow()))?;
+        }
+        seq.end()
+      }
+      Var::Object(val) => {
+        let val = val.borrow();
+        let mut map = serializer.serialize_map(Some(val.len()))?;
+        for (key, value) in val.iter() {
+          map.serialize_entry(&*key.as_str(), &self.with(&value.borrow()))?;
+        }
+        map.end()
+      }
+      Var::Map(val) => {
+        let val = val.borrow();
+        let mut map = serializer.serialize_map(Some(val.len()))?;
+        for (key, value) in val.iter() {
+          map.serialize_entry(&self.with(&Var::from(key)), &self.with(&value.borrow()))?;
+        }
+        map.end()
+      }
+      Var::Set(val) => {
+        let mut seq = serializer.serialize_seq(Some(val.len()))?;
+        for item in val.iter() {
+          seq.serialize_element(&self.with(&Var::from(item)))?;
+        }
+        seq.end()
+      }
+      Var::Function(_) | Var::Builtin(_) => Err(S::Error::custom("Function is not serializable")),
+      Var::Statement(_) => Err(S::Error::custom("Statement is not serializable")),
+      Var::Expression(_) => Err(S::Error::custom("Expression is not serializable")),
+    }
+  }
+}
+#[cfg(feature = "serde")]
+impl serde::Serialize for Serialized<'_> {
+  fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    if let Some(id) = self.val.container_id() {
+      if self.path.borrow().contains(&id) {
+        return Err(serde::ser::Error::custom(
+          "Circular structure is not serializable",
+        ));
+      }
+      self.path.borrow_mut().push(id);
+      let ret = self.serialize_inner(serializer);
+      self.path.borrow_mut().pop();
+      return ret;
+    }
+    self.serialize_inner(serializer)
+  }
+}
+#[cfg(feature = "serde")]
+struct VarVisitor;
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for VarVisitor {
+  type Value = Var;
+  fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    formatter.write_str("null, boolean, number, string, array or object")
+  }
+  fn visit_unit<E: serde::de::Error>(self) -> Result<Var, E> {
+    Ok(Var::Null(()))
+  }
+  fn visit_none<E: serde::de::Error>(self) -> Result<Var, E> {
+    Ok(Var::Null(()))
+  }
+  fn visit_some<D: serde::Deserializer<'de>>(self, deserializer: D) -> Result<Var, D::Error> {
+    serde::Deserialize::deserialize(deserializer)
+  }
+  fn visit_bool<E: serde::de::Error>(self, val: bool) -> Result<Var, E> {
+    Ok(Var::Boolean(val))
+  }
+  fn visit_i64<E: serde::de::Error>(self, val: i64) -> Result<Var, E> {
+    Ok(Var::Integer(val))
+  }
+  fn visit_u64<E: serde::de::Error>(self, val: u64) -> Result<Var, E> {
+    match i64::try_from(val) {
+      Ok(val) => Ok(Var::Integer(val)),
+      Err(_) => Ok(Var::Number(val as f64)),
+    }
+  }
+  fn visit_f64<E: serde::de::Error>(self, val: f64) -> Result<Var, E> {
+    Ok(Var::Number(val))
+  }
+  fn visit_str<E: serde::de::Error>(self, val: &str) -> Result<Var, E> {
+    Ok(Var::String(val.to_string()))
+  }
+  fn visit_string<E: serde::de::Error>(self, val: String) -> Result<Var, E> {
+    Ok(Var::String(val))
+  }
+  fn visit_bytes<E: serde::de::Error>(self, val: &[u8]) -> Result<Var, E> {
+    Ok(Var::Bytes(val.to_vec()))
+  }
+  fn visit_byte_buf<E: serde::de::Error>(self, val: Vec<u8>) -> Result<Var, E> {
+    Ok(Var::Bytes(val))
+  }
+  fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Var, A::Error> {
+    let mut ret: Vec<Rc<RefCell<Var>>> = vec![];
+    while let Some(item) = seq.next_element::<Var>()? {
+      ret.push(Rc::new(RefCell::new(item)));
+    }
+    Ok(Var::from(ret))
+  }
+  fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Var, A::Error> {
+    let mut ret: IndexMap<Symbol, Rc<RefCell<Var>>> = IndexMap::new();
+    while let Some((key, value)) = map.next_entry::<String, Var>()? {
+      ret.insert(Symbol::from(key), Rc::new(RefCell::new(value)));
+    }
+    Ok(Var::from(ret))
+  }
+}
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Var {
+  /// 从任意 serde 格式反序列化值。
+  /// 整数转换为 `Var::Integer`，超出 `i64` 范围时转换为 `Var::Number`。
+  fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Var, D::Error> {
+    deserializer.deserialize_any(VarVisitor)
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::super::testing::{call, handler, method, var};
+  use super::*;
+  /// `left op right` 的结果，出错时为 `error: ` 加上原因。
+  fn calc(left: &str, op: &str, right: &str) -> String {
+    match var(left).opcall(op, &var(right)) {
+      Ok(val) => val.to_string(),
+      Err(err) => format!("error: {}", err.err),
+    }
+  }
+  #[test]
+  fn bigint_arithmetic_is_exact() {
+    assert_eq!(
+      calc("123456789012345678901234567890n", "*", "10n"),
+      "1234567890123456789012345678900n"
+    );
+    assert_eq!(calc("0xffn", "+", "1"), "256n");
+    assert_eq!(calc("7n", "/", "2n"), "3n");
+    assert_eq!(calc("7n", "%", "0n"), "error: Division by zero");
+    assert_eq!(
+      calc("1n", "+", "1.5"),
+      "error: Cannot mix BigInt and Number"
+    );
+    assert_eq!(calc("2n", "==", "2.0"), "true");
+    assert_eq!(calc("2n", "===", "2"), "false");
+    assert_eq!(calc("99999999999999999999n", ">", "1e19"), "true");
+  }
+  #[test]
+  fn decimal_arithmetic_is_exact() {
+    assert_eq!(calc("0.1m", "+", "0.2m"), "0.3m");
+    assert_eq!(calc("0.1m", "+", "0.2m"), calc("0.3m", "+", "0m"));
+    assert_eq!(calc("1.5m", "*", "2"), "3.0m");
+    assert_eq!(calc("1m", "/", "0m"), "error: Division by zero");
+    assert_eq!(
+      calc("1.5m", "+", "1.5"),
+      "error: Cannot mix Decimal and Number"
+    );
+    assert_eq!(calc("1.5m", "<", "2.0"), "true");
+    DecimalContext::set(DecimalContext {
+      scale: 2,
+      rounding: RoundingStrategy::MidpointAwayFromZero,
+    });
+    let ret = calc("10m", "/", "3m");
+    DecimalContext::set(DecimalContext::new());
+    assert_eq!(ret, "3.33m");
+  }
+  #[test]
+  fn objects_keep_insertion_order() {
+    let handler = handler();
+    let obj = var("{\"b\":1,\"a\":2,\"c\":3}");
+    assert_eq!(obj.to_string(), "{\"b\":1,\"a\":2,\"c\":3}");
+    assert_eq!(obj.deep_clone().to_string(), obj.to_string());
+    call(&handler, "Object.delete", vec![obj.clone(), var("\"a\"")]);
+    call(
+      &handler,
+      "Object.assign",
+      vec![obj.clone(), var("{\"a\":4}")],
+    );
+    assert_eq!(
+      call(&handler, "Object.keys", vec![obj.clone()]).to_string(),
+      "[\"b\",\"c\",\"a\"]"
+    );
+    let text = call(&handler, "JSON.stringify", vec![obj]);
+    assert_eq!(
+      text.to_string(),
+      "\"{\\\"b\\\":1,\\\"c\\\":3,\\\"a\\\":4}\""
+    );
+    let back = call(&handler, "JSON.parse", vec![text]);
+    assert_eq!(back.to_string(), "{\"b\":1,\"c\":3,\"a\":4}");
+  }
+  #[test]
+  fn arrays_and_objects_are_shared_by_reference() {
+    let handler = handler();
+    let arr = var("[1,2]");
+    let alias = arr.clone();
+    method(&handler, alias, "push", vec![var("3")]);
+    assert_eq!(arr.to_string(), "[1,2,3]");
+    let copy = call(&handler, "structuredClone", vec![arr.clone()]);
+    method(&handler, copy.clone(), "push", vec![var("4")]);
+    assert_eq!(arr.to_string(), "[1,2,3]");
+    assert_eq!(copy.to_string(), "[1,2,3,4]");
+    let obj = var("{\"items\":[1]}");
+    let inner = match &obj {
+      Var::Object(val) => val.borrow()[&Symbol::from("items")].borrow().clone(),
+      _ => unreachable!(),
+    };
+    method(&handler, inner, "push", vec![var("2")]);
+    assert_eq!(obj.to_string(), "{\"items\":[1,2]}");
+  }
+  /// `{"self": <自身>, "items": [<自身>]}`
+  fn cyclic() -> Var {
+    let obj = var("{\"items\":[]}");
+    if let Var::Object(val) = &obj {
+      if let Var::Array(items) = &*val.borrow()[&Symbol::from("items")].borrow() {
+        items.borrow_mut().push(Rc::new(RefCell::new(obj.clone())));
+      }
+      val
+        .borrow_mut()
+        .insert(Symbol::from("self"), Rc::new(RefCell::new(obj.clone())));
+    }
+    obj
+  }
+  #[test]
+  fn cycles_are_safe_to_print_compare_and_clone() {
+    let (left, right) = (cyclic(), cyclic());
+    assert_eq!(
+      left.to_string(),
+      "{\"items\":[[Circular]],\"self\":[Circular]}"
+    );
+    assert!(matches!(
+      left.clone().opcall("==", &right),
+      Ok(Var::Boolean(true))
+    ));
+    let other = var("{\"items\":[1],\"self\":null}");
+    assert!(matches!(
+      left.clone().opcall("==", &other),
+      Ok(Var::Boolean(false))
+    ));
+    let copy = left.deep_clone();
+    assert_ne!(copy.container_id(), left.container_id());
+    if let Var::Object(val) = &copy {
+      let val = val.borrow();
+      assert_eq!(
+        val[&Symbol::from("self")].borrow().container_id(),
+        copy.container_id()
+      );
+      let items = val[&Symbol::from("items")].borrow().clone();
+      if let Var::Array(items) = items {
+        assert_eq!(
+          items.borrow()[0].borrow().container_id(),
+          copy.container_id()
+        );
+      }
+    }
+    assert!(matches!(copy.opcall("==", &left), Ok(Var::Boolean(true))));
+  }
+  #[test]
+  fn numbers_round_trip_through_to_string() {
+    for val in [3.0, 0.5, 1e21, 1.5e-7, 1e-300, 123456.0] {
+      let text = Var::Number(val).to_string();
+      match Var::parse(&text) {
+        Ok(Var::Number(ret)) => assert_eq!(ret, val, "{}", text),
+        _ => panic!("{} does not parse back as a Number", text),
+      }
+    }
+    assert_eq!(Var::Number(3.0).to_string(), "3.0");
+    assert_eq!(Var::Number(1e21).to_string(), "1e21");
+    assert_eq!(Var::Integer(3).to_string(), "3");
+  }
+  #[test]
+  fn signed_exponents_are_not_operators() {
+    assert!(matches!(var("1e-7"), Var::Number(val) if val == 1e-7));
+    assert!(matches!(var("1.5e+3"), Var::Number(val) if val == 1500.0));
+    assert!(ExprValue::isexp("1e-7-1"));
+    assert!(ExprValue::isexp("e-1"));
+    assert!(ExprValue::isexp("0x1e-1"));
+    match ExprValue::parse("2.5e-1+1e+2") {
+      Ok(ExprValue::Expr((op, left, right))) => {
+        assert_eq!(
+          (op.as_str(), left.as_str(), right.as_str()),
+          ("+", "2.5e-1", "1e+2")
+        )
+      }
+      _ => panic!("expected a binary expression"),
+    }
+    match ExprValue::parse("2+13") {
+      Ok(ExprValue::Expr((_, left, right))) => {
+        assert_eq!((left.as_str(), right.as_str()), ("2", "13"))
+      }
+      _ => panic!("expected a binary expression"),
+    }
+  }
+  #[test]
+  fn string_conversion_keeps_plain_numbers() {
+    assert_eq!(
+      String::try_from(Var::Number(3.0)).ok(),
+      Some(String::from("3"))
+    );
+  }
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_round_trips_data() {
+    let val = var("{\"a\":[1,2.5,null,true],\"b\":\"x\"}");
+    let text = serde_json::to_string(&val).ok().unwrap();
+    assert_eq!(text, "{\"a\":[1,2.5,null,true],\"b\":\"x\"}");
+    let back: Var = serde_json::from_str(&text).ok().unwrap();
+    assert!(matches!(back.opcall("==", &val), Ok(Var::Boolean(true))));
+    let big: Var = serde_json::from_str("18446744073709551615").ok().unwrap();
+    assert!(matches!(big, Var::Number(_)));
+    assert_eq!(
+      serde_json::to_string(&var("1.50m")).ok().unwrap(),
+      "\"1.50\""
+    );
+  }
+  #[cfg(feature = "serde")]
+  #[test]
+  fn serde_rejects_code_and_cycles() {
+    assert!(serde_json::to_string(&var("func(){}")).is_err());
+    let arr = var("[]");
+    if let Var::Array(val) = &arr {
+      val.borrow_mut().push(Rc::new(RefCell::new(arr.clone())));
+    }
+    assert!(serde_json::to_string(&arr).is_err());
+    if let Var::Array(val) = &arr {
+      val.borrow_mut().clear();
+    }
+  }
+}